@@ -0,0 +1,279 @@
+use std::{
+  collections::HashMap,
+  future::Future,
+  net::IpAddr,
+  path::{Path, PathBuf},
+  time::Instant,
+};
+
+use log::info;
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::Value;
+use specta::Type;
+use tauri::{AppHandle, Manager, State};
+use tokio::net::lookup_host;
+use url::Url;
+
+use crate::{
+  db::{get_settings, settings::Probe},
+  error::{map_any_error, map_anything, Result},
+  geodat::{load_geoip, load_geosite, Cidr, Domain, DomainKind, GeoIp},
+  xray::routing_rules,
+};
+
+use super::endpoint::{probe_client, XrayState};
+
+/// 诊断的一个步骤
+#[derive(Clone, Debug, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnoseStep {
+  /// 是否成功
+  pub ok: bool,
+  /// 耗时，毫秒
+  pub elapsed: i32,
+  /// 结果，如解析到的地址或状态码
+  pub detail: Option<String>,
+  /// 错误
+  pub error: Option<String>,
+}
+
+impl DiagnoseStep {
+  /// 执行一个步骤并计时
+  async fn run<F>(f: F) -> Self
+  where
+    F: Future<Output = Result<String>>,
+  {
+    let now = Instant::now();
+    let result = f.await;
+    let elapsed = now.elapsed().as_millis() as i32;
+
+    match result {
+      Ok(detail) => Self {
+        ok: true,
+        elapsed,
+        detail: Some(detail),
+        error: None,
+      },
+      Err(e) => Self {
+        ok: false,
+        elapsed,
+        detail: None,
+        error: Some(e.to_string()),
+      },
+    }
+  }
+
+  /// 无法执行的步骤
+  fn skipped(reason: &str) -> Self {
+    Self {
+      error: Some(String::from(reason)),
+      ..Default::default()
+    }
+  }
+}
+
+/// 匹配到的路由规则
+#[derive(Clone, Debug, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteMatch {
+  /// 规则序号，从 0 开始；为空时表示没有匹配的规则，交给节点
+  pub index: Option<u32>,
+  /// 出站 tag
+  pub outbound_tag: String,
+  /// 匹配的条目，如 geosite:cn
+  pub matched: Option<String>,
+  /// 无法判断时的错误，如缺少 geosite.dat
+  pub error: Option<String>,
+}
+
+/// 站点诊断结果
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct WebsiteDiagnosis {
+  /// URL
+  pub url: String,
+  /// 主机名
+  pub host: String,
+  /// 域名解析
+  pub dns: DiagnoseStep,
+  /// 直接访问
+  pub direct: DiagnoseStep,
+  /// 通过当前节点的 SOCKS 入站访问
+  pub proxy: DiagnoseStep,
+  /// 路由规则
+  pub route: RouteMatch,
+}
+
+/// 诊断站点无法访问的原因：域名解析、直接访问、通过代理访问，以及会匹配的路由规则
+#[tauri::command]
+#[specta::specta]
+pub async fn diagnose_website(app: AppHandle, url: String) -> Result<WebsiteDiagnosis> {
+  let parsed = Url::parse(&url).map_err(map_any_error)?;
+  let host = parsed
+    .host_str()
+    .ok_or_else(|| map_anything("No host in URL"))?
+    .trim_matches(['[', ']'])
+    .to_lowercase();
+  let port = parsed.port_or_known_default().unwrap_or(443);
+  let settings = get_settings(&app).await?;
+  info!("Diagnosing {}", &url);
+
+  let dns = DiagnoseStep::run(async {
+    let addrs: Vec<_> = lookup_host((host.as_str(), port))
+      .await?
+      .map(|addr| addr.ip().to_string())
+      .collect();
+    Ok(addrs.join(", "))
+  })
+  .await;
+
+  let direct = DiagnoseStep::run(fetch(None, &url, &settings.probe)).await;
+
+  let running = {
+    let state: State<XrayState> = app.state();
+    let xray_guard = state.xray.lock().await;
+    xray_guard.as_ref().and_then(|xray| xray.port()).is_some()
+  };
+  let proxy = if running {
+    DiagnoseStep::run(fetch(Some(settings.socks_port), &url, &settings.probe)).await
+  } else {
+    DiagnoseStep::skipped("No current endpoint")
+  };
+
+  let route = app
+    .path_resolver()
+    .app_data_dir()
+    .ok_or_else(|| map_anything("No app data dir"))
+    .and_then(|data_dir| match_route(data_dir, &settings.rule, &host))
+    .unwrap_or_else(|e| RouteMatch {
+      error: Some(e.to_string()),
+      ..Default::default()
+    });
+
+  Ok(WebsiteDiagnosis {
+    url,
+    host,
+    dns,
+    direct,
+    proxy,
+    route,
+  })
+}
+
+/// 访问 URL，返回状态码
+async fn fetch(proxy_port: Option<u16>, url: &str, probe: &Probe) -> Result<String> {
+  let client = probe_client(proxy_port, probe)?;
+  let response = client.request(Method::GET, url).send().await?;
+  Ok(format!("HTTP {}", response.status()))
+}
+
+/// 按路由规则的顺序查找第一个匹配的规则
+///
+/// 路由使用 AsIs 策略，目标是域名时不会解析后再匹配 IP 规则。
+fn match_route(data_dir: PathBuf, rule: &str, host: &str) -> Result<RouteMatch> {
+  let ip: Option<IpAddr> = host.parse().ok();
+  let mut matcher = RuleMatcher::new(&data_dir);
+
+  for (index, rule) in routing_rules(rule).iter().enumerate() {
+    let matched = match ip {
+      Some(ip) => matcher.first_match(&rule["ip"], |m, entry| m.match_ip(entry, &ip))?,
+      None => matcher.first_match(&rule["domain"], |m, entry| m.match_domain(entry, host))?,
+    };
+
+    if let Some(matched) = matched {
+      return Ok(RouteMatch {
+        index: Some(index as u32),
+        outbound_tag: String::from(rule["outboundTag"].as_str().unwrap_or_default()),
+        matched: Some(matched),
+        error: None,
+      });
+    }
+  }
+
+  Ok(RouteMatch {
+    outbound_tag: String::from("proxy"),
+    ..Default::default()
+  })
+}
+
+/// 路由规则匹配，缓存读取过的 geosite 和 geoip 分组
+struct RuleMatcher<'a> {
+  data_dir: &'a Path,
+  geosites: HashMap<String, Vec<Domain>>,
+  geoips: HashMap<String, GeoIp>,
+}
+
+impl<'a> RuleMatcher<'a> {
+  fn new(data_dir: &'a Path) -> Self {
+    Self {
+      data_dir,
+      geosites: HashMap::new(),
+      geoips: HashMap::new(),
+    }
+  }
+
+  /// 返回列表中第一个匹配的条目
+  fn first_match<F>(&mut self, entries: &Value, mut f: F) -> Result<Option<String>>
+  where
+    F: FnMut(&mut Self, &str) -> Result<bool>,
+  {
+    if let Some(entries) = entries.as_array() {
+      for entry in entries.iter().filter_map(|entry| entry.as_str()) {
+        if f(self, entry)? {
+          return Ok(Some(String::from(entry)));
+        }
+      }
+    }
+
+    Ok(None)
+  }
+
+  fn match_domain(&mut self, entry: &str, host: &str) -> Result<bool> {
+    if let Some(name) = entry.strip_prefix("geosite:") {
+      let (code, attr) = match name.split_once('@') {
+        Some((code, attr)) => (code, Some(attr)),
+        None => (name, None),
+      };
+
+      if !self.geosites.contains_key(code) {
+        let domains = load_geosite(&self.data_dir.join("geosite.dat"), code)?;
+        self.geosites.insert(String::from(code), domains);
+      }
+
+      return Ok(self.geosites[code].iter().any(|domain| {
+        domain.matches(host)
+          && attr.map_or(true, |attr| domain.attributes.iter().any(|a| a == attr))
+      }));
+    }
+
+    let (kind, value) = match entry.split_once(':') {
+      Some(("domain", value)) => (DomainKind::Domain, value),
+      Some(("full", value)) => (DomainKind::Full, value),
+      Some(("regexp", value)) => (DomainKind::Regex, value),
+      Some(("keyword", value)) => (DomainKind::Plain, value),
+      _ => (DomainKind::Plain, entry),
+    };
+
+    let domain = Domain::new(kind, String::from(value), Vec::new());
+    Ok(domain.matches(host))
+  }
+
+  fn match_ip(&mut self, entry: &str, ip: &IpAddr) -> Result<bool> {
+    if let Some(code) = entry.strip_prefix("geoip:") {
+      let (code, negate) = match code.strip_prefix('!') {
+        Some(code) => (code, true),
+        None => (code, false),
+      };
+
+      if !self.geoips.contains_key(code) {
+        let geoip = load_geoip(&self.data_dir.join("geoip.dat"), code)?;
+        self.geoips.insert(String::from(code), geoip);
+      }
+
+      return Ok(self.geoips[code].contains(ip) != negate);
+    }
+
+    Ok(Cidr::parse(entry).is_some_and(|cidr| cidr.contains(ip)))
+  }
+}
@@ -1,71 +1,462 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use log::{debug, info};
+use log::{debug, error, info, warn};
 use ormlite::TableMeta;
 use ormlite::{
   model::{HasModelBuilder, ModelBuilder},
   Executor, Model,
 };
-use tauri::{async_runtime::Mutex, AppHandle, Manager, State};
+use regex::Regex;
+use reqwest::{redirect::Policy, Method};
+use serde::Serialize;
+use specta::Type;
+use tauri::{
+  async_runtime::{spawn, Mutex},
+  AppHandle, Manager, State,
+};
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
+use tokio::time::timeout;
 use tokio_js_set_interval::{clear_interval, set_interval_async};
 
 use crate::app_handle::get_app_handle;
 use crate::{
   db::{
-    db_query_endpoints, endpoint::Endpoint, get_settings, notify_change, select,
-    settings::Settings, DbState,
+    db_query_endpoints, db_query_websites, db_set_settings,
+    endpoint::Endpoint,
+    endpoint_group::{get_endpoint_group, resolve_group_members},
+    endpoint_settings::{find_endpoint, query_endpoint_settings},
+    get_settings,
+    latency::{
+      insert_latency_logs, query_website_latencies, remove_expired_latency_logs,
+      save_website_latency, LatencyStats, TestOutcome, TestResult, WebsiteLatency,
+    },
+    notify_change, select,
+    settings::{Balancer, PingMode, Probe, ProbeMethod, Settings, SpeedTest},
+    website::Website,
+    DbState,
   },
-  error::Result,
+  error::{map_any_error, map_anything, Result},
+  ping::ping_endpoint,
   xray::Xray,
 };
 
-use super::query_stats::query_all_stats;
+use super::{
+  network::{check_network, ensure_online, NetworkStatus},
+  query_stats::{query_all_stats, query_balancer},
+  ranking::{keep_current, rank_endpoints},
+  test_run::{run_test, ProgressCounter},
+};
+
+/// 每个测试用 xray 进程包含的节点数量
+const TEST_BATCH_SIZE: usize = 128;
 
 #[derive(Default)]
 pub struct XrayState {
   pub xray: Arc<Mutex<Option<Xray>>>,
   pub stats_timer_id: Arc<Mutex<u64>>,
   pub check_timer_id: Arc<Mutex<u64>>,
+  /// 最近一次切换节点的时间
+  pub switched_at: Arc<Mutex<Option<Instant>>>,
+  /// 当前节点连续过慢的检查次数
+  pub slow_checks: Arc<Mutex<u32>>,
+  /// 锁定当前节点，本次运行期间不自动切换
+  pub locked: Arc<Mutex<bool>>,
+  /// 当前使用的节点分组
+  pub group_id: Arc<Mutex<Option<i64>>>,
 }
 
-/// 测试全部节点的连接速度
-async fn test_latencies(app: AppHandle) -> Result<()> {
+/// 测试全部节点的连接速度，返回测速 ID；测速被取消时为空
+async fn test_latencies(app: AppHandle) -> Result<Option<u64>> {
   let state: State<DbState> = app.state();
-  {
-    let mut db_guard = state.db.lock().await;
-    let db = db_guard.as_mut().expect("Database not intialized");
-    let sql = format!("UPDATE {} SET latency = -1", Endpoint::table_name());
-    db.execute(sql.as_str()).await?;
-    notify_change::<Endpoint>(&app)?;
+  let eps: Vec<_> = db_query_endpoints(state)
+    .await?
+    .into_iter()
+    .filter(|ep| !ep.disabled.unwrap_or_default())
+    .collect();
+  info!("Testing latencies for all {} endpoints", eps.len());
+
+  let run_id = start_tests(&app, eps, TestJob::Latency, true).await?;
+
+  if let Some(run_id) = run_id {
+    info!("All endpoints tested in run {}", run_id);
   }
 
-  let eps = db_query_endpoints(state).await?;
-  info!("Testing latencies for all {} endpoints", eps.len());
+  Ok(run_id)
+}
 
-  let settings = get_settings(&app).await?;
+/// 测试内容
+#[derive(Clone)]
+enum TestJob {
+  /// 测试 ep_test_url 的延迟
+  Latency,
+  /// 测试访问各个站点的延迟
+  Websites(Arc<Vec<Website>>),
+}
+
+/// 启动测速并等待完成，返回测速 ID；测速被取消时为空。full 表示测试全部节点
+async fn start_tests(
+  app: &AppHandle,
+  eps: Vec<Endpoint>,
+  job: TestJob,
+  full: bool,
+) -> Result<Option<u64>> {
+  ensure_online(app).await?;
+  let app = app.clone();
+
+  let run_id = run_test(full, move |run_id| async move {
+    if let Err(e) = run_tests(&app, eps, job, run_id).await {
+      warn!("Test run {} error: {:?}", run_id, e);
+    }
+  })
+  .await;
+  Ok(run_id)
+}
+
+/// 标记节点为正在测试；保留之前的延迟，取消时据此恢复
+async fn mark_testing(app: &AppHandle, eps: &[Endpoint]) -> Result<()> {
+  if eps.is_empty() {
+    return Ok(());
+  }
+
+  let ids: Vec<_> = eps.iter().map(|ep| ep.id.to_string()).collect();
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+  let sql = format!(
+    "UPDATE {} SET outcome = '{}' WHERE id IN ({})",
+    Endpoint::table_name(),
+    TestOutcome::Testing,
+    ids.join(","),
+  );
+  db.execute(sql.as_str()).await?;
+  notify_change::<Endpoint>(app)?;
+
+  Ok(())
+}
+
+/// 测试一组节点的连接速度
+async fn run_tests(app: &AppHandle, eps: Vec<Endpoint>, job: TestJob, run_id: u64) -> Result<()> {
+  let progress = Arc::new(ProgressCounter::new(app, run_id, eps.len()));
+  let settings = get_settings(app).await?;
+  let body_regex = settings.probe.body_regex().map_err(map_anything)?;
+
+  if let TestJob::Websites(_) = job {
+    return run_batches(app, eps, &job, &settings, body_regex.as_ref(), progress).await;
+  }
+
+  mark_testing(app, &eps).await?;
+  remove_expired_latency_logs(app).await?;
+
+  let eps = if settings.ep_ping_mode != PingMode::Off {
+    prefilter(app, eps, &settings, &progress).await?
+  } else {
+    eps
+  };
+
+  run_batches(app, eps, &job, &settings, body_regex.as_ref(), progress).await
+}
+
+/// 每个 xray 进程测试一批节点，并发量由信号量控制
+async fn run_batches(
+  app: &AppHandle,
+  eps: Vec<Endpoint>,
+  job: &TestJob,
+  settings: &Settings,
+  body_regex: Option<&Regex>,
+  progress: Arc<ProgressCounter>,
+) -> Result<()> {
   let sem = Arc::new(Semaphore::new(settings.ep_test_concurrency as usize));
+  let mut batches = JoinSet::new();
 
-  // 这里得一个一个地起 xray，因为每次都要不同的端口
-  let mut tests = JoinSet::new();
+  for chunk in eps.chunks(TEST_BATCH_SIZE) {
+    let app = app.clone();
+    let job = job.clone();
+    let settings = settings.clone();
+    let body_regex = body_regex.cloned();
+    let sem = Arc::clone(&sem);
+    let progress = Arc::clone(&progress);
+    let eps = chunk.to_vec();
+
+    batches.spawn(async move {
+      if let Err(e) = test_batch(
+        &app,
+        eps,
+        &job,
+        &settings,
+        body_regex.as_ref(),
+        sem,
+        progress,
+      )
+      .await
+      {
+        warn!("Batch test error: {:?}", e);
+      }
+    });
+  }
+
+  while batches.join_next().await.is_some() {}
+  Ok(())
+}
+
+/// 重新读取节点，取得测试结果
+async fn reload_endpoints(app: &AppHandle, eps: &[Endpoint]) -> Result<Vec<Endpoint>> {
+  let mut items = Vec::with_capacity(eps.len());
+
+  for ep in eps {
+    items.push(select(app, ep.id).await?);
+  }
+
+  Ok(items)
+}
+
+/// 测试一个节点，不切换当前节点
+#[tauri::command]
+#[specta::specta]
+pub async fn test_endpoint_latency(app: AppHandle, ep_id: i64) -> Result<Endpoint> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+
+  if ep.disabled.unwrap_or_default() {
+    return Err(map_anything(
+      ep.disabled_reason
+        .unwrap_or(String::from("Endpoint disabled")),
+    ));
+  }
+
+  ensure_online(&app).await?;
+  let settings = get_settings(&app).await?;
+  let body_regex = settings.probe.body_regex().map_err(map_anything)?;
+  mark_testing(&app, std::slice::from_ref(&ep)).await?;
+  test_endpoint(
+    &app,
+    Xray::new(ep),
+    &TestJob::Latency,
+    &settings,
+    body_regex.as_ref(),
+  )
+  .await?;
+
+  select(&app, ep_id).await
+}
+
+/// 测试指定的节点，不切换当前节点
+#[tauri::command]
+#[specta::specta]
+pub async fn test_endpoint_latencies(app: AppHandle, ep_ids: Vec<i64>) -> Result<Vec<Endpoint>> {
+  let mut eps = Vec::with_capacity(ep_ids.len());
+
+  for ep_id in ep_ids {
+    let ep: Endpoint = select(&app, ep_id).await?;
+
+    if !ep.disabled.unwrap_or_default() {
+      eps.push(ep);
+    }
+  }
+
+  info!("Testing latencies for {} endpoints", eps.len());
+  start_tests(&app, eps.clone(), TestJob::Latency, false).await?;
+  reload_endpoints(&app, &eps).await
+}
+
+/// 测试订阅下的全部节点，不切换当前节点
+#[tauri::command]
+#[specta::specta]
+pub async fn test_subscription_latencies(app: AppHandle, sub_id: i64) -> Result<Vec<Endpoint>> {
+  let eps = {
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+
+    Endpoint::select()
+      .where_bind("sub_id = ?", sub_id)
+      .where_("NOT IFNULL(disabled, 0)")
+      .fetch_all(db)
+      .await?
+  };
+
+  info!(
+    "Testing latencies for {} endpoints of sub {}",
+    eps.len(),
+    sub_id
+  );
+  start_tests(&app, eps.clone(), TestJob::Latency, false).await?;
+  reload_endpoints(&app, &eps).await
+}
+
+/// 测试全部节点访问各个站点的延迟，不切换当前节点
+#[tauri::command]
+#[specta::specta]
+pub async fn test_website_matrix(app: AppHandle) -> Result<()> {
+  let websites = db_query_websites(app.state()).await?;
+  test_websites(&app, websites).await
+}
+
+/// 测试全部节点访问指定站点的延迟
+async fn test_websites(app: &AppHandle, websites: Vec<Website>) -> Result<()> {
+  let eps: Vec<_> = db_query_endpoints(app.state())
+    .await?
+    .into_iter()
+    .filter(|ep| !ep.disabled.unwrap_or_default())
+    .collect();
+  info!(
+    "Testing {} websites for {} endpoints",
+    websites.len(),
+    eps.len()
+  );
+
+  let job = TestJob::Websites(Arc::new(websites));
+  start_tests(app, eps, job, false).await?;
+  notify_change::<WebsiteLatency>(app)
+}
+
+/// 测试全部节点访问指定站点的延迟，并选择最快的节点
+#[tauri::command]
+#[specta::specta]
+pub async fn select_fastest_endpoint_for_website(app: AppHandle, website_id: i64) -> Result<i64> {
+  let website: Website = select(&app, website_id).await?;
+  info!("Selecting fastest endpoint for {}", &website.name);
+  test_websites(&app, vec![website]).await?;
+
+  let latencies: HashMap<_, _> = query_website_latencies(&app, Some(website_id))
+    .await?
+    .into_iter()
+    .filter_map(|item| Some(((item.host, item.port), item.latency?)))
+    .collect();
+  let ep = db_query_endpoints(app.state())
+    .await?
+    .into_iter()
+    .filter(|ep| !ep.disabled.unwrap_or_default())
+    .filter_map(|ep| Some((*latencies.get(&(ep.host.clone(), ep.port))?, ep)))
+    .min_by_key(|(latency, _)| *latency)
+    .map(|(_, ep)| ep)
+    .ok_or_else(|| map_anything("No available endpoint"))?;
+
+  set_current_endpoint(app, ep.id).await?;
+  Ok(ep.id)
+}
+
+/// 直接连接节点做快速预检，不通的节点记为失败，返回需要继续测试的节点
+async fn prefilter(
+  app: &AppHandle,
+  eps: Vec<Endpoint>,
+  settings: &Settings,
+  progress: &ProgressCounter,
+) -> Result<Vec<Endpoint>> {
+  let ep_settings: HashMap<_, _> = query_endpoint_settings(app)
+    .await?
+    .into_iter()
+    .map(|s| ((s.host.clone(), s.port), s))
+    .collect();
+  let sem = Arc::new(Semaphore::new(settings.ep_test_concurrency as usize));
+  let mut passed = Vec::new();
+  let mut pings = JoinSet::new();
 
   for ep in eps {
+    let doc = ep_settings.get(&(ep.host.clone(), ep.port));
+
+    // 经过前置代理的节点未必能直接连接
+    if doc.and_then(|doc| doc.upstream()).is_some() {
+      passed.push(ep);
+      continue;
+    }
+
+    let details = ep.details(doc.and_then(|doc| doc.patch.as_deref()));
+    let sni = match details.security.as_str() {
+      "tls" | "reality" => Some(details.sni.unwrap_or_else(|| ep.host.clone())),
+      _ => None,
+    };
+    let mode = settings.ep_ping_mode;
     let permit = Arc::clone(&sem).acquire_owned().await;
-    let app = app.clone();
-    let settings = settings.clone();
-    let xray = Xray::new(ep);
 
-    tests.spawn(async move {
+    pings.spawn(async move {
       let _permit = permit;
-      let _ = test_endpoint(&app, xray, &settings).await;
+      let result = ping_endpoint(&ep, sni.as_deref(), mode).await;
+      (ep, result)
     });
   }
 
-  while let Some(_) = tests.join_next().await {}
-  info!("All endpoints tested");
-  Ok(())
+  while let Some(joined) = pings.join_next().await {
+    if let Ok((ep, result)) = joined {
+      match result {
+        Ok(latency) => {
+          debug!("Endpoint {} ping {}", ep.id, latency);
+          passed.push(ep);
+        }
+        Err(outcome) => {
+          info!("Endpoint {} ping failed: {}", ep.id, outcome);
+          let _ = save_samples(app, ep, vec![Err(outcome)]).await;
+          progress.tick();
+        }
+      }
+    }
+  }
+
+  info!("{} endpoints passed ping", passed.len());
+  Ok(passed)
+}
+
+/// 用一个 xray 进程测试一批节点
+async fn test_batch(
+  app: &AppHandle,
+  eps: Vec<Endpoint>,
+  job: &TestJob,
+  settings: &Settings,
+  body_regex: Option<&Regex>,
+  sem: Arc<Semaphore>,
+  progress: Arc<ProgressCounter>,
+) -> Result<()> {
+  let mut xray = Xray::new_batch(eps.clone());
+  let started = match xray.start("test").await {
+    Ok(()) => xray.wait_for_started().await,
+    Err(e) => Err(e),
+  };
+
+  let mut tests = JoinSet::new();
+
+  if let Err(e) = started {
+    // 个别节点的配置有问题时整个进程都起不来，退回到逐个测试
+    warn!("Batch xray failed to start: {:?}, testing one by one", e);
+    xray.stop().await?;
+
+    for ep in eps {
+      let permit = Arc::clone(&sem).acquire_owned().await;
+      let app = app.clone();
+      let job = job.clone();
+      let settings = settings.clone();
+      let body_regex = body_regex.cloned();
+      let progress = Arc::clone(&progress);
+
+      tests.spawn(async move {
+        let _permit = permit;
+        let xray = Xray::new(ep);
+        let _ = test_endpoint(&app, xray, &job, &settings, body_regex.as_ref()).await;
+        progress.tick();
+      });
+    }
+  } else {
+    let ports = xray.test_ports().to_vec();
+
+    for (ep, port) in eps.into_iter().zip(ports) {
+      let permit = Arc::clone(&sem).acquire_owned().await;
+      let app = app.clone();
+      let job = job.clone();
+      let settings = settings.clone();
+      let body_regex = body_regex.cloned();
+      let progress = Arc::clone(&progress);
+
+      tests.spawn(async move {
+        let _permit = permit;
+        info!("Testing endpoint {} - {}", ep.id, &ep.name);
+        let _ = test_on_port(&app, ep, port, &job, &settings, body_regex.as_ref()).await;
+        progress.tick();
+      });
+    }
+  }
+
+  while tests.join_next().await.is_some() {}
+  xray.stop().await
 }
 
 /// 设置当前节点
@@ -85,21 +476,110 @@ pub async fn get_current_endpoint(state: State<'_, XrayState>) -> Result<Option<
 #[specta::specta]
 pub async fn set_current_endpoint(app: AppHandle, ep_id: i64) -> Result<()> {
   let ep: Endpoint = select(&app, ep_id).await?;
+
+  if ep.disabled.unwrap_or_default() {
+    return Err(map_anything(
+      ep.disabled_reason
+        .unwrap_or(String::from("Endpoint disabled")),
+    ));
+  }
+
   let settings = get_settings(&app).await?;
   info!("Set current endpoint {:?}", &ep);
 
+  // 负载均衡时加上排名靠前的其他节点；锁定时只用这一个节点
+  let state: State<XrayState> = app.state();
+  let locked = *state.locked.lock().await;
+  let xray = if settings.balancer.enabled && !locked {
+    let others: Vec<_> = rank_endpoints(&app, &settings)
+      .await?
+      .into_iter()
+      .map(|c| c.ep)
+      .filter(|other| other.id != ep.id)
+      .take(settings.balancer.size.saturating_sub(1) as usize)
+      .collect();
+    info!("Balancing with {} other endpoints", others.len());
+
+    let eps = std::iter::once(ep).chain(others).collect();
+    Xray::new_balancer(eps, settings.balancer.clone())
+  } else {
+    Xray::new(ep)
+  };
+
+  start_current(&app, xray, &settings, None).await
+}
+
+/// 使用节点分组，由 xray 对组内节点负载均衡
+///
+/// 与 `set_current_endpoint` 分开，单个节点仍按 ID 设置。使用分组时，
+/// `get_current_endpoint` 返回组内第一个可用节点的 ID，`get_current_group` 返回分组 ID；
+/// 自动选择和故障切换都把分组视为锁定，不会更换。调用 `set_current_endpoint` 即退出分组。
+#[tauri::command]
+#[specta::specta]
+pub async fn set_current_group(app: AppHandle, group_id: i64) -> Result<()> {
+  let group = get_endpoint_group(&app, group_id).await?;
+  let eps = resolve_group_members(&app, &group).await?;
+
+  if eps.is_empty() {
+    return Err(map_anything("No available endpoint in group"));
+  }
+
+  let settings = get_settings(&app).await?;
+  info!(
+    "Set current group {} with {} endpoints",
+    &group.name,
+    eps.len()
+  );
+
+  let balancer = Balancer {
+    enabled: true,
+    size: eps.len() as u32,
+    strategy: group.strategy,
+    ..settings.balancer.clone()
+  };
+  let xray = Xray::new_balancer(eps, balancer);
+
+  start_current(&app, xray, &settings, Some(group_id)).await
+}
+
+/// 获取当前使用的节点分组
+#[tauri::command]
+#[specta::specta]
+pub async fn get_current_group(state: State<'_, XrayState>) -> Result<Option<i64>> {
+  Ok(*state.group_id.lock().await)
+}
+
+/// 停止原有的 xray，启动新的作为当前节点
+async fn start_current(
+  app: &AppHandle,
+  mut xray: Xray,
+  settings: &Settings,
+  group_id: Option<i64>,
+) -> Result<()> {
   let state: State<XrayState> = app.state();
   let mut xray_guard = state.xray.lock().await;
 
-  if let Some(mut xray) = xray_guard.take() {
-    xray.stop().await?;
+  // 只是修改配置后重启时，保留停留时间和过慢次数
+  let mut switched = *state.group_id.lock().await != group_id;
+
+  if let Some(mut old) = xray_guard.take() {
+    let (ep, old_ep) = (xray.endpoint(), old.endpoint());
+    switched |= ep.host != old_ep.host || ep.port != old_ep.port;
+    old.stop().await?;
+  } else {
+    switched = true;
   }
 
-  let mut xray = Xray::new(ep);
   xray.start(&settings.rule).await?;
   xray.wait_for_started().await?;
   let port = xray.port().unwrap_or_default();
   *xray_guard = Some(xray);
+  *state.group_id.lock().await = group_id;
+
+  if switched {
+    *state.switched_at.lock().await = Some(Instant::now());
+    *state.slow_checks.lock().await = 0;
+  }
 
   app.emit_all("app://endpoint/current", ())?;
 
@@ -108,6 +588,221 @@ pub async fn set_current_endpoint(app: AppHandle, ep_id: i64) -> Result<()> {
   Ok(())
 }
 
+/// 更新订阅后节点 ID 会变化，按地址和端口找到当前节点的新记录
+///
+/// 出站都没有变化时只更新记录，不重启 xray，以免断开现有连接；否则重新启动。
+/// 当前节点已不存在时解除锁定。
+pub async fn rebind_current_endpoint(app: &AppHandle) -> Result<()> {
+  let (current, group_id) = {
+    let state: State<XrayState> = app.state();
+    let mut xray_guard = state.xray.lock().await;
+    let Some(xray) = xray_guard.as_mut() else {
+      return Ok(());
+    };
+    let mut found = Vec::new();
+
+    for ep in xray.endpoints() {
+      match find_endpoint(app, &ep.host, ep.port).await? {
+        Some(new) if !new.disabled.unwrap_or_default() && new.outbound == ep.outbound => {
+          found.push(new)
+        }
+        _ => break,
+      }
+    }
+
+    if found.len() == xray.endpoints().len() {
+      debug!("Current endpoints unchanged, rebinding without restart");
+      xray.rebind(found);
+      drop(xray_guard);
+      app.emit_all("app://endpoint/current", ())?;
+      return Ok(());
+    }
+
+    let current = xray.endpoint().clone();
+    let group_id = *state.group_id.lock().await;
+    (current, group_id)
+  };
+
+  // 使用分组时重新查找组内节点；都不存在时退回到单个节点
+  if let Some(group_id) = group_id {
+    match set_current_group(app.clone(), group_id).await {
+      Ok(()) => return Ok(()),
+      Err(e) => warn!("Failed to restart group {}: {:?}", group_id, e),
+    }
+  }
+
+  match find_endpoint(app, &current.host, current.port).await? {
+    Some(ep) if !ep.disabled.unwrap_or_default() => {
+      set_current_endpoint(app.clone(), ep.id).await?;
+    }
+    _ => {
+      info!("Current endpoint {} is gone", &current.name);
+      let state: State<XrayState> = app.state();
+      *state.locked.lock().await = false;
+    }
+  }
+
+  Ok(())
+}
+
+/// 锁定的当前节点；使用节点分组时由 xray 切换，也视为锁定
+async fn locked_endpoint(app: &AppHandle) -> Option<i64> {
+  let state: State<XrayState> = app.state();
+
+  if !*state.locked.lock().await && state.group_id.lock().await.is_none() {
+    return None;
+  }
+
+  let xray_guard = state.xray.lock().await;
+  xray_guard.as_ref().map(|xray| xray.endpoint().id)
+}
+
+/// 负载均衡的状态
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct BalancerStatus {
+  /// 负载均衡中的节点 ID，首选的在前
+  pub endpoint_ids: Vec<i64>,
+  /// xray 选中的节点 ID，正在使用的在前
+  pub selected_ids: Vec<i64>,
+  /// 正在使用的节点 ID
+  pub active_id: Option<i64>,
+}
+
+/// 通过 xray API 查询负载均衡正在使用的节点；未启用负载均衡时为空
+#[tauri::command]
+#[specta::specta]
+pub async fn get_balancer_status(state: State<'_, XrayState>) -> Result<Option<BalancerStatus>> {
+  let (endpoint_ids, api_port) = {
+    let xray_guard = state.xray.lock().await;
+    let Some(xray) = xray_guard.as_ref() else {
+      return Ok(None);
+    };
+    let Some(eps) = xray.balanced_endpoints() else {
+      return Ok(None);
+    };
+    let ids: Vec<i64> = eps.iter().map(|ep| ep.id).collect();
+    (ids, xray.port())
+  };
+
+  let Some(api_port) = api_port else {
+    return Ok(None);
+  };
+
+  let selected_ids: Vec<i64> = query_balancer(api_port, "proxy")
+    .await?
+    .iter()
+    .filter_map(|tag| {
+      let i: usize = tag.strip_prefix("proxy-")?.parse().ok()?;
+      endpoint_ids.get(i).copied()
+    })
+    .collect();
+
+  Ok(Some(BalancerStatus {
+    active_id: selected_ids.first().copied(),
+    endpoint_ids,
+    selected_ids,
+  }))
+}
+
+/// 当前节点是否已锁定
+#[tauri::command]
+#[specta::specta]
+pub async fn get_endpoint_locked(state: State<'_, XrayState>) -> Result<bool> {
+  Ok(*state.locked.lock().await)
+}
+
+/// 锁定当前节点，本次运行期间自动选择和故障切换都不会更换节点
+///
+/// 启用负载均衡时，锁定后只使用当前节点。
+#[tauri::command]
+#[specta::specta]
+pub async fn set_endpoint_locked(app: AppHandle, locked: bool) -> Result<()> {
+  info!("Set endpoint locked {}", locked);
+  let state: State<XrayState> = app.state();
+  let changed = std::mem::replace(&mut *state.locked.lock().await, locked) != locked;
+
+  // 负载均衡时 xray 会切换到其他节点，锁定或解锁后重新启动
+  if changed && get_settings(&app).await?.balancer.enabled {
+    restart_current_endpoint(&app).await?;
+  }
+
+  app.emit_all("app://endpoint/current", ())?;
+  Ok(())
+}
+
+/// 重新启动当前节点，使修改后的配置生效
+pub async fn restart_current_endpoint(app: &AppHandle) -> Result<()> {
+  let (ep_id, group_id) = {
+    let state: State<XrayState> = app.state();
+    let xray_guard = state.xray.lock().await;
+    let ep_id = xray_guard.as_ref().map(|xray| xray.endpoint().id);
+    (ep_id, *state.group_id.lock().await)
+  };
+
+  // 分组无法使用时，如已被删除，退回到单个节点
+  if let Some(group_id) = group_id {
+    match set_current_group(app.clone(), group_id).await {
+      Ok(()) => return Ok(()),
+      Err(e) => warn!("Failed to restart group {}: {:?}", group_id, e),
+    }
+  }
+
+  if let Some(ep_id) = ep_id {
+    set_current_endpoint(app.clone(), ep_id).await?;
+  }
+
+  Ok(())
+}
+
+/// 修改分组后，如果正在使用该分组，重新启动使修改生效
+pub async fn reload_current_group(app: &AppHandle, group_id: i64) -> Result<()> {
+  let state: State<XrayState> = app.state();
+
+  if *state.group_id.lock().await != Some(group_id) {
+    return Ok(());
+  }
+
+  info!("Current group {} updated", group_id);
+  restart_current_endpoint(app).await
+}
+
+/// 删除分组时，如果正在使用该分组，改为只使用当前节点
+pub async fn leave_current_group(app: &AppHandle, group_id: i64) -> Result<()> {
+  let ep_id = {
+    let state: State<XrayState> = app.state();
+    let xray_guard = state.xray.lock().await;
+    let mut group_guard = state.group_id.lock().await;
+
+    if *group_guard != Some(group_id) {
+      return Ok(());
+    }
+
+    *group_guard = None;
+    xray_guard.as_ref().map(|xray| xray.endpoint().id)
+  };
+
+  info!("Current group {} removed", group_id);
+
+  if let Some(ep_id) = ep_id {
+    set_current_endpoint(app.clone(), ep_id).await?;
+  }
+
+  Ok(())
+}
+
+/// 快速开关 TLS 分片和 UDP 噪声，无需重新导入订阅
+#[tauri::command]
+#[specta::specta]
+pub async fn set_fragment_enabled(app: AppHandle, enabled: bool) -> Result<()> {
+  let mut settings = get_settings(&app).await?;
+  info!("Set fragment enabled {}", enabled);
+  settings.fragment.enabled = enabled;
+  db_set_settings(app.state(), settings).await?;
+
+  restart_current_endpoint(&app).await
+}
+
 async fn start_query_stats(state: &State<'_, XrayState>, api_port: u16) {
   let mut guard = state.stats_timer_id.lock().await;
 
@@ -122,42 +817,123 @@ async fn start_query_stats(state: &State<'_, XrayState>, api_port: u16) {
   };
 }
 
-/// 给所有节点测速，并选择最快的节点
+/// 给所有节点测速，并按得分选择最好的节点
+///
+/// 网络不正常时跳过，返回当前节点；没有当前节点时为空。
 #[tauri::command]
 #[specta::specta]
-pub async fn select_fastest_endpoint(app: AppHandle) -> Result<i64> {
+pub async fn select_fastest_endpoint(app: AppHandle) -> Result<Option<i64>> {
+  if let Some(current_id) = locked_endpoint(&app).await {
+    info!("Endpoint {} is locked", current_id);
+    return Ok(Some(current_id));
+  }
+
+  if check_network(&app).await != NetworkStatus::Online {
+    info!("Network is not available, skipping endpoint selection");
+    let state: State<XrayState> = app.state();
+    return get_current_endpoint(state).await;
+  }
+
   info!("Selecting fastest endpoint");
-  test_latencies(app.clone()).await?;
 
-  let ep = {
-    let state: State<DbState> = app.state();
-    let mut db_guard = state.db.lock().await;
-    let db = db_guard.as_mut().expect("Database not intialized");
+  if test_latencies(app.clone()).await?.is_none() {
+    info!("Latency test cancelled, keeping current endpoint");
+    let state: State<XrayState> = app.state();
+    return get_current_endpoint(state).await;
+  }
 
-    Endpoint::select()
-      .where_bind("latency > ?", 0)
-      .order_asc("latency")
-      .limit(1)
-      .fetch_one(db)
-      .await?
-  };
+  let settings = get_settings(&app).await?;
+  let ranked = rank_endpoints(&app, &settings).await?;
 
+  if let Some(current_id) = keep_current(&app, &settings, &ranked).await {
+    return Ok(Some(current_id));
+  }
+
+  let ep = ranked
+    .into_iter()
+    .next()
+    .map(|c| c.ep)
+    .ok_or_else(|| map_anything("No available endpoint"))?;
   set_current_endpoint(app, ep.id).await?;
-  Ok(ep.id)
+  Ok(Some(ep.id))
 }
 
-async fn test_endpoint(app: &AppHandle, xray: Xray, settings: &Settings) -> Result<()> {
+async fn test_endpoint(
+  app: &AppHandle,
+  mut xray: Xray,
+  job: &TestJob,
+  settings: &Settings,
+  body_regex: Option<&Regex>,
+) -> Result<()> {
   let ep = xray.endpoint().clone();
   info!("Testing endpoint {} - {}", ep.id, &ep.name);
 
-  let latency = test_xray(xray, settings).await.unwrap_or(999999);
-  debug!("Endpoint {} latency {}", ep.id, latency);
+  let started = match xray.start("test").await {
+    Ok(()) => xray.wait_for_started().await,
+    Err(e) => Err(e),
+  };
+  let port = match started {
+    Ok(()) => xray.port(),
+    Err(e) => {
+      warn!("Xray failed to start for endpoint {}: {:?}", ep.id, e);
+      None
+    }
+  };
+
+  let result = test_on_port(app, ep, port, job, settings, body_regex).await;
+  xray.stop().await?;
+  result
+}
+
+/// 通过 xray 的端口测试节点并保存结果；端口为空表示 xray 没有启动
+async fn test_on_port(
+  app: &AppHandle,
+  ep: Endpoint,
+  port: Option<u16>,
+  job: &TestJob,
+  settings: &Settings,
+  body_regex: Option<&Regex>,
+) -> Result<()> {
+  match job {
+    TestJob::Latency => {
+      let samples = match port {
+        Some(port) => sample_port(port, settings, body_regex).await,
+        None => vec![Err(TestOutcome::XrayFailed)],
+      };
+      save_samples(app, ep, samples).await
+    }
+
+    TestJob::Websites(websites) => {
+      for website in websites.iter() {
+        let result = match port {
+          Some(port) => test_port(port, &website.url, &settings.probe, body_regex).await,
+          None => Err(TestOutcome::XrayFailed),
+        };
+        debug!("Endpoint {} website {} {:?}", ep.id, &website.name, &result);
+        save_website_latency(app, &ep, website.id, result).await?;
+      }
+
+      Ok(())
+    }
+  }
+}
+
+/// 保存测速样本，节点延迟取中位数
+async fn save_samples(app: &AppHandle, ep: Endpoint, samples: Vec<TestResult>) -> Result<()> {
+  let latencies: Vec<_> = samples.iter().map(|r| r.ok()).collect();
+  let stats = LatencyStats::from_samples(&latencies);
+  let outcome = TestOutcome::from_samples(&samples);
+  debug!("Endpoint {} {} latency {:?}", ep.id, outcome, &stats);
+  insert_latency_logs(app, &ep, &samples).await?;
 
   let state: State<DbState> = app.state();
   let mut db_guard = state.db.lock().await;
   let db = db_guard.as_mut().expect("Database not intialized");
   ep.update_partial()
-    .latency(Some(latency))
+    .latency(stats.median)
+    .jitter(stats.jitter)
+    .loss(Some(stats.loss))
+    .outcome(Some(outcome.to_string()))
     .update(db)
     .await?;
 
@@ -166,36 +942,232 @@ async fn test_endpoint(app: &AppHandle, xray: Xray, settings: &Settings) -> Resu
   Ok(())
 }
 
-async fn test_xray(mut xray: Xray, settings: &Settings) -> Result<i32> {
+/// 多次测试端口
+async fn sample_port(
+  proxy_port: u16,
+  settings: &Settings,
+  body_regex: Option<&Regex>,
+) -> Vec<TestResult> {
+  let url = &settings.ep_test_url;
+  let mut samples = Vec::new();
+
+  for _ in 0..settings.ep_test_samples.max(1) {
+    samples.push(test_port(proxy_port, url, &settings.probe, body_regex).await);
+  }
+
+  samples
+}
+
+/// 测试一次端口；内容匹配的正则由调用方编译好传入
+async fn test_port(
+  proxy_port: u16,
+  url: &str,
+  probe: &Probe,
+  body_regex: Option<&Regex>,
+) -> TestResult {
+  let method = match probe.method {
+    ProbeMethod::Get => Method::GET,
+    ProbeMethod::Head if body_regex.is_some() => Method::GET,
+    ProbeMethod::Head => Method::HEAD,
+  };
+  let client = probe_client(Some(proxy_port), probe).map_err(|e| TestOutcome::from_error(&e))?;
+
+  if probe.real_delay {
+    // 第一次请求建立连接，第二次请求复用连接
+    probe_once(&client, &method, url, probe, None).await?;
+  }
+
+  probe_once(&client, &method, url, probe, body_regex).await
+}
+
+/// 按测试请求设置创建客户端；不指定端口时直接连接
+pub(crate) fn probe_client(
+  proxy_port: Option<u16>,
+  probe: &Probe,
+) -> reqwest::Result<reqwest::Client> {
+  let redirect = if probe.follow_redirects {
+    Policy::default()
+  } else {
+    Policy::none()
+  };
+
+  let builder = reqwest::Client::builder()
+    .timeout(Duration::from_secs(probe.timeout.max(1) as u64))
+    .redirect(redirect)
+    .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0");
+
+  let builder = match proxy_port {
+    Some(port) => {
+      let proxy_url = format!("socks5://127.0.0.1:{}", port);
+      builder.proxy(reqwest::Proxy::all(proxy_url)?)
+    }
+    None => builder.no_proxy(),
+  };
+
+  builder.build()
+}
+
+/// 发送一次测试请求，返回收到响应头的时间
+async fn probe_once(
+  client: &reqwest::Client,
+  method: &Method,
+  url: &str,
+  probe: &Probe,
+  body_regex: Option<&Regex>,
+) -> TestResult {
+  let now = Instant::now();
+  let response = client
+    .request(method.clone(), url)
+    .send()
+    .await
+    .map_err(|e| TestOutcome::from_error(&e))?;
+  let elapsed = now.elapsed().as_millis() as i32;
+
+  let status = response.status();
+  let expected = if probe.expected_status.is_empty() {
+    status.is_success()
+  } else {
+    probe.expected_status.contains(&status.as_u16())
+  };
+
+  if !expected {
+    return Err(TestOutcome::HttpStatus(status.as_u16()));
+  }
+
+  // 读完响应内容，连接才能复用
+  let body = response
+    .text()
+    .await
+    .map_err(|e| TestOutcome::from_error(&e))?;
+
+  if let Some(re) = body_regex {
+    if !re.is_match(&body) {
+      return Err(TestOutcome::BodyMismatch);
+    }
+  }
+
+  Ok(elapsed)
+}
+
+/// 测试节点的下载速度，不切换当前节点
+#[tauri::command]
+#[specta::specta]
+pub async fn test_speeds(app: AppHandle, ep_ids: Vec<i64>) -> Result<()> {
+  ensure_online(&app).await?;
+  let settings = get_settings(&app).await?;
+  let sem = Arc::new(Semaphore::new(
+    settings.speed_test.concurrency.max(1) as usize
+  ));
+  let mut tests = JoinSet::new();
+
+  for ep_id in ep_ids {
+    let ep: Endpoint = select(&app, ep_id).await?;
+
+    if ep.disabled.unwrap_or_default() {
+      continue;
+    }
+
+    let permit = Arc::clone(&sem).acquire_owned().await;
+    let app = app.clone();
+    let speed_test = settings.speed_test.clone();
+
+    tests.spawn(async move {
+      let _permit = permit;
+      info!("Testing speed of endpoint {} - {}", ep.id, &ep.name);
+
+      let speed = match speed_xray(Xray::new(ep.clone()), &speed_test).await {
+        Ok(speed) => Some(speed),
+        Err(e) => {
+          warn!("Speed test of endpoint {} failed: {:?}", ep.id, e);
+          None
+        }
+      };
+      let _ = save_speed(&app, ep, speed).await;
+    });
+  }
+
+  while tests.join_next().await.is_some() {}
+  info!("Speed test finished");
+  Ok(())
+}
+
+/// 保存下载速度
+async fn save_speed(app: &AppHandle, ep: Endpoint, speed: Option<i64>) -> Result<()> {
+  debug!("Endpoint {} speed {:?}", ep.id, speed);
+
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+  ep.update_partial().speed(speed).update(db).await?;
+
+  notify_change::<Endpoint>(app)?;
+
+  Ok(())
+}
+
+async fn speed_xray(mut xray: Xray, speed_test: &SpeedTest) -> Result<i64> {
   xray.start("test").await?;
   xray.wait_for_started().await?;
 
-  let latency = test_port(xray.port().unwrap(), &settings.ep_test_url)
-    .await
-    .unwrap_or(999999);
+  let speed = speed_port(xray.port().unwrap(), speed_test).await;
   xray.stop().await?;
 
-  Ok(latency)
+  speed
 }
 
-async fn test_port(proxy_port: u16, url: &String) -> Result<i32> {
+/// 通过端口下载，返回从收到第一块数据开始计算的平均速度，字节每秒
+async fn speed_port(proxy_port: u16, speed_test: &SpeedTest) -> Result<i64> {
+  let duration = Duration::from_secs(speed_test.duration.max(1) as u64);
+  let max_bytes = speed_test.max_mb.max(1) as u64 * 1024 * 1024;
+  let wait = Duration::from_secs(10);
+
   let proxy_url = format!("socks5://127.0.0.1:{}", proxy_port);
   let client = reqwest::Client::builder()
-    .timeout(Duration::from_secs(10))
+    .connect_timeout(wait)
     .proxy(reqwest::Proxy::all(proxy_url)?)
     .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0")
     .build()?;
 
-  let now = Instant::now();
-  let status = client.head(url).send().await?.status();
-  let elapsed = now.elapsed().as_millis() as i32;
+  let mut response = timeout(wait, client.get(&speed_test.url).send())
+    .await
+    .map_err(map_any_error)??
+    .error_for_status()?;
 
-  if status.is_success() {
-    Ok(elapsed)
-  } else {
-    // 999999 表示超时或失败
-    Ok(999999)
+  // 第一块数据包含了握手的时间，不计入速度
+  let mut started: Option<Instant> = None;
+  let mut bytes = 0u64;
+
+  loop {
+    let remaining = match started {
+      Some(started) => duration.saturating_sub(started.elapsed()),
+      None => wait,
+    };
+
+    let chunk = match timeout(remaining, response.chunk()).await {
+      Ok(Ok(Some(chunk))) => chunk,
+      Ok(Ok(None)) => break,
+      Ok(Err(e)) if started.is_none() => return Err(e.into()),
+      _ => break,
+    };
+
+    if started.is_none() {
+      started = Some(Instant::now());
+    } else {
+      bytes += chunk.len() as u64;
+    }
+
+    if bytes >= max_bytes {
+      break;
+    }
   }
+
+  let elapsed = started.map(|s| s.elapsed()).unwrap_or_default();
+
+  if bytes == 0 || elapsed.is_zero() {
+    return Err(map_anything("No data received"));
+  }
+
+  Ok((bytes as f64 / elapsed.as_secs_f64()) as i64)
 }
 
 /// 启动自动检查当前节点
@@ -218,7 +1190,9 @@ pub async fn start_check_current_endpoint() -> Result<()> {
 
   *guard = set_interval_async!(
     || tokio::task::spawn(async {
-      check_current_endpoint().await.unwrap();
+      if let Err(e) = check_current_endpoint().await {
+        error!("Failed to check current endpoint: {:?}", e);
+      }
     }),
     interval
   );
@@ -228,33 +1202,141 @@ pub async fn start_check_current_endpoint() -> Result<()> {
 
 async fn check_current_endpoint() -> Result<()> {
   let app = get_app_handle().expect("No app handle");
-  let started = {
+  let (current_id, balanced) = {
     let state: State<XrayState> = app.state();
     let xray = state.xray.lock().await;
+    let current = xray
+      .as_ref()
+      .filter(|xray| xray.port().unwrap_or_default() > 0);
+    (
+      current.map(|xray| xray.endpoint().id),
+      current.is_some_and(|xray| xray.balanced_endpoints().is_some()),
+    )
+  };
+  debug!("Checking current endpoint {:?}", current_id);
+
+  if balanced {
+    debug!("Balancer enabled, xray checks endpoints itself");
+    return Ok(());
+  }
+
+  if let Some(current_id) = current_id {
+    let settings = get_settings(&app).await?;
+    let selection = &settings.selection;
+    let body_regex = settings.probe.body_regex().map_err(map_anything)?;
+
+    match test_port(
+      settings.socks_port,
+      &settings.ep_test_url,
+      &settings.probe,
+      body_regex.as_ref(),
+    )
+    .await
+    {
+      Ok(latency) if selection.slow_latency > 0 && latency > selection.slow_latency as i32 => {
+        let slow_checks = {
+          let state: State<XrayState> = app.state();
+          let mut guard = state.slow_checks.lock().await;
+          *guard += 1;
+          *guard
+        };
+        info!(
+          "Current latency {} is slow ({} times)",
+          latency, slow_checks
+        );
 
-    if let Some(xray) = xray.as_ref() {
-      if xray.port().unwrap_or_default() > 0 {
-        true
-      } else {
-        false
+        if slow_checks >= selection.slow_checks.max(1) {
+          failover(
+            &app,
+            &settings,
+            current_id,
+            Some(selection.slow_latency as i32),
+          )
+          .await?;
+        }
+      }
+      Ok(latency) => {
+        info!("Current latency {}", latency);
+        let state: State<XrayState> = app.state();
+        *state.slow_checks.lock().await = 0;
+      }
+      Err(outcome) => {
+        info!("Current endpoint failed: {}", outcome);
+        failover(&app, &settings, current_id, None).await?;
       }
-    } else {
-      false
     }
-  };
-  debug!("Checking current endpoint {}", started);
+  }
 
-  if started {
-    let settings = get_settings(&app).await?;
-    let latency = test_port(settings.socks_port, &settings.ep_test_url)
-      .await
-      .unwrap_or(999999);
-    info!("Current latency {}", latency);
+  Ok(())
+}
+
+/// 切换到最近测速排名靠前且仍然可用的节点，然后在后台重新测速排名
+///
+/// 指定 max_latency 时，候选节点的延迟也要低于它。都不可用时重新测试全部节点。
+async fn failover(
+  app: &AppHandle,
+  settings: &Settings,
+  current_id: i64,
+  max_latency: Option<i32>,
+) -> Result<()> {
+  if locked_endpoint(app).await.is_some() {
+    info!("Endpoint {} is locked, skipping failover", current_id);
+    return Ok(());
+  }
+
+  // 本机断网时所有节点都会失败，不要切换
+  if check_network(app).await != NetworkStatus::Online {
+    info!("Network is not available, skipping failover");
+    return Ok(());
+  }
 
-    if latency >= 999999 {
-      select_fastest_endpoint(app).await?;
+  let candidates: Vec<_> = rank_endpoints(app, settings)
+    .await?
+    .into_iter()
+    .filter(|c| c.ep.id != current_id)
+    .take(settings.selection.failover_candidates as usize)
+    .collect();
+  let body_regex = settings.probe.body_regex().map_err(map_anything)?;
+
+  for candidate in candidates {
+    let ep = candidate.ep;
+
+    match verify_endpoint(ep.clone(), settings, body_regex.as_ref()).await {
+      Ok(latency) if max_latency.map_or(true, |max| latency < max) => {
+        info!("Failing over to endpoint {} ({}ms)", ep.id, latency);
+        set_current_endpoint(app.clone(), ep.id).await?;
+
+        let app = app.clone();
+        spawn(async move {
+          if let Err(e) = select_fastest_endpoint(app).await {
+            warn!("Failed to refresh endpoint ranking: {:?}", e);
+          }
+        });
+        return Ok(());
+      }
+      Ok(latency) => info!("Failover candidate {} is slow ({}ms)", ep.id, latency),
+      Err(e) => info!("Failover candidate {} failed: {:?}", ep.id, e),
     }
   }
 
+  info!("No failover candidate available, testing all endpoints");
+  select_fastest_endpoint(app.clone()).await?;
   Ok(())
 }
+
+/// 通过临时的 xray 进程快速测试一个节点
+async fn verify_endpoint(
+  ep: Endpoint,
+  settings: &Settings,
+  body_regex: Option<&Regex>,
+) -> Result<i32> {
+  let mut xray = Xray::new(ep);
+  xray.start("test").await?;
+  xray.wait_for_started().await?;
+
+  let port = xray.port().unwrap();
+  let result = test_port(port, &settings.ep_test_url, &settings.probe, body_regex).await;
+  xray.stop().await?;
+
+  result.map_err(|outcome| map_anything(outcome.to_string()))
+}
@@ -0,0 +1,211 @@
+use log::info;
+use ormlite::types::Json;
+use serde::Serialize;
+use serde_json::Value;
+use specta::Type;
+use tauri::AppHandle;
+
+use crate::{
+  db::{
+    endpoint::Endpoint,
+    endpoint_settings::{
+      find_endpoint, get_endpoint_settings, get_endpoint_settings_or_default, resolve_upstreams,
+      save_endpoint_settings,
+    },
+    select,
+    settings::{Fragment, Mux},
+  },
+  error::{map_anything, Result},
+};
+
+use super::endpoint::restart_current_endpoint;
+
+/// 获取节点的前置代理
+#[tauri::command]
+#[specta::specta]
+pub async fn get_endpoint_upstream(app: AppHandle, ep_id: i64) -> Result<Option<i64>> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let upstream = get_endpoint_settings(&app, &ep.host, ep.port)
+    .await?
+    .and_then(|s| s.upstream());
+
+  if let Some((host, port)) = upstream {
+    Ok(find_endpoint(&app, &host, port).await?.map(|ep| ep.id))
+  } else {
+    Ok(None)
+  }
+}
+
+/// 设置节点的前置代理，为空时直接连接
+#[tauri::command]
+#[specta::specta]
+pub async fn set_endpoint_upstream(
+  app: AppHandle,
+  ep_id: i64,
+  upstream_id: Option<i64>,
+) -> Result<()> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let mut settings = get_endpoint_settings_or_default(&app, &ep).await?;
+
+  if let Some(upstream_id) = upstream_id {
+    let upstream: Endpoint = select(&app, upstream_id).await?;
+
+    // 前置代理的链路中不能出现自己
+    let chain = resolve_upstreams(&app, &upstream).await?;
+    if std::iter::once(&upstream)
+      .chain(chain.iter())
+      .any(|u| u.host == ep.host && u.port == ep.port)
+    {
+      return Err(map_anything("Upstream would create a cycle"));
+    }
+
+    info!("Set upstream of {} to {}", &ep.name, &upstream.name);
+    settings.upstream_host = Some(upstream.host);
+    settings.upstream_port = Some(upstream.port);
+  } else {
+    info!("Clear upstream of {}", &ep.name);
+    settings.upstream_host = None;
+    settings.upstream_port = None;
+  }
+
+  save_endpoint_settings(&app, settings).await?;
+  restart_current_endpoint(&app).await
+}
+
+/// 获取节点的多路复用设置，为空时使用全局设置
+#[tauri::command]
+#[specta::specta]
+pub async fn get_endpoint_mux(app: AppHandle, ep_id: i64) -> Result<Option<Mux>> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let settings = get_endpoint_settings(&app, &ep.host, ep.port).await?;
+  Ok(settings.and_then(|s| s.mux).map(|mux| mux.0))
+}
+
+/// 设置节点的多路复用，为空时使用全局设置
+#[tauri::command]
+#[specta::specta]
+pub async fn set_endpoint_mux(app: AppHandle, ep_id: i64, mux: Option<Mux>) -> Result<()> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let mut settings = get_endpoint_settings_or_default(&app, &ep).await?;
+  settings.mux = mux.map(Json);
+
+  save_endpoint_settings(&app, settings).await?;
+  restart_current_endpoint(&app).await
+}
+
+/// 获取节点的分片设置，为空时使用全局设置
+#[tauri::command]
+#[specta::specta]
+pub async fn get_endpoint_fragment(app: AppHandle, ep_id: i64) -> Result<Option<Fragment>> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let settings = get_endpoint_settings(&app, &ep.host, ep.port).await?;
+  Ok(settings.and_then(|s| s.fragment).map(|fragment| fragment.0))
+}
+
+/// 设置节点的分片，为空时使用全局设置
+#[tauri::command]
+#[specta::specta]
+pub async fn set_endpoint_fragment(
+  app: AppHandle,
+  ep_id: i64,
+  fragment: Option<Fragment>,
+) -> Result<()> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let mut settings = get_endpoint_settings_or_default(&app, &ep).await?;
+  settings.fragment = fragment.map(Json);
+
+  save_endpoint_settings(&app, settings).await?;
+  restart_current_endpoint(&app).await
+}
+
+/// 获取用户对节点出站对象的修改
+#[tauri::command]
+#[specta::specta]
+pub async fn get_endpoint_patch(app: AppHandle, ep_id: i64) -> Result<Option<String>> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let settings = get_endpoint_settings(&app, &ep.host, ep.port).await?;
+  Ok(settings.and_then(|s| s.patch))
+}
+
+/// 设置用户对节点出站对象的修改，更新订阅后仍然有效
+#[tauri::command]
+#[specta::specta]
+pub async fn set_endpoint_patch(app: AppHandle, ep_id: i64, patch: String) -> Result<()> {
+  let value: Value = serde_json::from_str(&patch)?;
+
+  if !value.is_object() {
+    return Err(map_anything("Patch must be a JSON object"));
+  }
+
+  let ep: Endpoint = select(&app, ep_id).await?;
+
+  // 确认修改后的出站对象仍然可用
+  ep.outbound_with_patch(Some(patch.as_str()))?;
+
+  let mut settings = get_endpoint_settings_or_default(&app, &ep).await?;
+  info!("Set patch of {} to {}", &ep.name, &patch);
+  settings.patch = Some(value.to_string());
+
+  save_endpoint_settings(&app, settings).await?;
+  restart_current_endpoint(&app).await
+}
+
+/// 清除用户对节点出站对象的修改
+#[tauri::command]
+#[specta::specta]
+pub async fn clear_endpoint_patch(app: AppHandle, ep_id: i64) -> Result<()> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let mut settings = get_endpoint_settings_or_default(&app, &ep).await?;
+  info!("Clear patch of {}", &ep.name);
+  settings.patch = None;
+
+  save_endpoint_settings(&app, settings).await?;
+  restart_current_endpoint(&app).await
+}
+
+/// 节点的收藏和黑名单标记
+#[derive(Clone, Debug, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointFlags {
+  /// 收藏，自动选择时优先使用
+  pub favorite: bool,
+  /// 黑名单，自动选择时不使用
+  pub blacklisted: bool,
+}
+
+/// 获取节点的收藏和黑名单标记
+#[tauri::command]
+#[specta::specta]
+pub async fn get_endpoint_flags(app: AppHandle, ep_id: i64) -> Result<EndpointFlags> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let settings = get_endpoint_settings(&app, &ep.host, ep.port).await?;
+
+  Ok(settings.map_or_else(Default::default, |s| EndpointFlags {
+    favorite: s.favorite.unwrap_or_default(),
+    blacklisted: s.blacklisted.unwrap_or_default(),
+  }))
+}
+
+/// 收藏节点，自动选择时优先使用
+#[tauri::command]
+#[specta::specta]
+pub async fn set_endpoint_favorite(app: AppHandle, ep_id: i64, favorite: bool) -> Result<()> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let mut settings = get_endpoint_settings_or_default(&app, &ep).await?;
+  info!("Set favorite of {} to {}", &ep.name, favorite);
+  settings.favorite = Some(favorite);
+
+  save_endpoint_settings(&app, settings).await
+}
+
+/// 把节点加入黑名单，自动选择时不使用；手动选择不受影响
+#[tauri::command]
+#[specta::specta]
+pub async fn set_endpoint_blacklisted(app: AppHandle, ep_id: i64, blacklisted: bool) -> Result<()> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let mut settings = get_endpoint_settings_or_default(&app, &ep).await?;
+  info!("Set blacklisted of {} to {}", &ep.name, blacklisted);
+  settings.blacklisted = Some(blacklisted);
+
+  save_endpoint_settings(&app, settings).await
+}
@@ -7,9 +7,14 @@ use crate::{
   error::{map_any_error, map_anything, Result},
 };
 
+pub mod diagnose;
 pub mod endpoint;
+pub mod endpoint_settings;
+pub mod network;
 pub mod query_stats;
+pub mod ranking;
 pub mod subscription;
+pub mod test_run;
 
 /// 获取可用于侦听的 TCP 端口
 pub fn get_available_port() -> Result<u16> {
@@ -0,0 +1,102 @@
+use std::{sync::Mutex, time::Duration};
+
+use log::{info, warn};
+use reqwest::{redirect::Policy, StatusCode};
+use serde::Serialize;
+use specta::Type;
+use tauri::{AppHandle, Manager};
+
+use crate::error::{map_anything, Result};
+
+/// 直接访问的连通性检测地址，正常时返回 204
+const CHECK_URLS: [&str; 3] = [
+  "http://connect.rom.miui.com/generate_204",
+  "http://connectivitycheck.gstatic.com/generate_204",
+  "http://cp.cloudflare.com/generate_204",
+];
+
+/// 本机网络状态，变化时通过 app://network 事件发送
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum NetworkStatus {
+  /// 正常
+  Online,
+  /// 无法连接网络
+  Offline,
+  /// 需要在认证页面登录，如酒店、机场的 Wi-Fi
+  CaptivePortal,
+}
+
+static STATUS: Mutex<Option<NetworkStatus>> = Mutex::new(None);
+
+/// 不经过代理访问检测地址，判断本机网络状态
+///
+/// 任一地址返回 204 即为正常；有响应但都不是 204 时，一般是被认证页面劫持了。
+pub async fn check_network(app: &AppHandle) -> NetworkStatus {
+  let status = match probe_network().await {
+    Ok(status) => status,
+    Err(e) => {
+      warn!("Failed to check network: {:?}", e);
+      NetworkStatus::Offline
+    }
+  };
+
+  let changed = STATUS.lock().unwrap().replace(status) != Some(status);
+
+  if changed {
+    info!("Network status {:?}", status);
+
+    if let Err(e) = app.emit_all("app://network", status) {
+      warn!("Failed to emit network status: {:?}", e);
+    }
+  }
+
+  status
+}
+
+async fn probe_network() -> Result<NetworkStatus> {
+  let client = reqwest::Client::builder()
+    .timeout(Duration::from_secs(5))
+    .redirect(Policy::none())
+    .no_proxy()
+    .build()?;
+  let mut status = NetworkStatus::Offline;
+
+  for url in CHECK_URLS {
+    match client.get(url).send().await {
+      Ok(response) if response.status() == StatusCode::NO_CONTENT => {
+        return Ok(NetworkStatus::Online)
+      }
+      Ok(response) => {
+        info!("Unexpected status {} from {}", response.status(), url);
+        status = NetworkStatus::CaptivePortal;
+      }
+      Err(e) => info!("Failed to reach {}: {:?}", url, e),
+    }
+  }
+
+  Ok(status)
+}
+
+/// 网络不正常时返回错误，避免把所有节点都记为失败
+pub async fn ensure_online(app: &AppHandle) -> Result<()> {
+  match check_network(app).await {
+    NetworkStatus::Online => Ok(()),
+    status => Err(map_anything(format!(
+      "Network is not available: {:?}",
+      status
+    ))),
+  }
+}
+
+/// 获取本机网络状态；还没有检测过时立即检测
+#[tauri::command]
+#[specta::specta]
+pub async fn get_network_status(app: AppHandle) -> NetworkStatus {
+  let status = *STATUS.lock().unwrap();
+
+  match status {
+    Some(status) => status,
+    None => check_network(&app).await,
+  }
+}
@@ -63,13 +63,26 @@ async fn query_stats(api_port: u16) -> Result<XrayStats> {
   };
 
   for stat in obj.stat {
-    match stat.name.as_str() {
-      "outbound>>>proxy>>>traffic>>>uplink" => {
-        stats.total_upload = stat.value.unwrap_or_default().parse().unwrap_or_default();
-      }
-      "outbound>>>proxy>>>traffic>>>downlink" => {
-        stats.total_download = stat.value.unwrap_or_default().parse().unwrap_or_default();
-      }
+    let parts: Vec<_> = stat.name.split(">>>").collect();
+
+    // 负载均衡时累加各节点的出站 proxy-序号
+    let ["outbound", tag, "traffic", direction] = parts.as_slice() else {
+      continue;
+    };
+    let is_proxy = *tag == "proxy"
+      || tag
+        .strip_prefix("proxy-")
+        .is_some_and(|i| i.parse::<u32>().is_ok());
+
+    if !is_proxy {
+      continue;
+    }
+
+    let value: i64 = stat.value.unwrap_or_default().parse().unwrap_or_default();
+
+    match *direction {
+      "uplink" => stats.total_upload += value,
+      "downlink" => stats.total_download += value,
       _ => {}
     }
   }
@@ -90,6 +103,66 @@ async fn query_sys(api_port: u16) -> Result<SysObject> {
   Ok(obj)
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct OverrideInfo {
+  #[serde(default)]
+  target: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PrincipleTargetInfo {
+  #[serde(default)]
+  tag: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BalancerMsg {
+  #[serde(default, rename = "override")]
+  override_info: Option<OverrideInfo>,
+  #[serde(default, alias = "principle_target")]
+  principle_target: Option<PrincipleTargetInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BalancerInfo {
+  #[serde(default)]
+  balancer: BalancerMsg,
+}
+
+/// 通过 API 查询负载均衡选中的出站 tag，正在使用的在前
+pub(crate) async fn query_balancer(api_port: u16, tag: &str) -> Result<Vec<String>> {
+  let output = Command::new_sidecar("xray")?
+    .args([
+      "api",
+      "bi",
+      "--json",
+      &format!("--server=127.0.0.1:{}", api_port),
+      tag,
+    ])
+    .output()?;
+  let info: BalancerInfo = serde_json::from_str(&output.stdout)?;
+
+  // 手动指定的出站优先
+  let mut tags: Vec<String> = info
+    .balancer
+    .override_info
+    .map(|o| o.target)
+    .filter(|target| !target.is_empty())
+    .into_iter()
+    .collect();
+
+  if let Some(principle) = info.balancer.principle_target {
+    for tag in principle.tag {
+      if !tags.contains(&tag) {
+        tags.push(tag);
+      }
+    }
+  }
+
+  Ok(tags)
+}
+
 /// 查询所有数据并上报
 pub(crate) async fn query_all_stats(api_port: u16) -> Result<()> {
   if let Some(app) = get_app_handle() {
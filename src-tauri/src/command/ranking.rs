@@ -0,0 +1,291 @@
+use std::{
+  collections::HashMap,
+  time::{Duration, Instant},
+};
+
+use log::{debug, info};
+use ormlite::Model;
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+  db::{
+    endpoint::Endpoint,
+    endpoint_settings::query_endpoint_settings,
+    latency::{latency_stats_by_endpoint, TestOutcome},
+    settings::{Selection, Settings},
+    DbState,
+  },
+  error::Result,
+};
+
+use super::endpoint::XrayState;
+
+/// 参与选择的节点
+#[derive(Clone, Debug)]
+pub struct Candidate {
+  pub ep: Endpoint,
+  /// 得分，相当于毫秒，越小越好
+  pub score: f64,
+  /// 是否收藏
+  pub favorite: bool,
+}
+
+/// 按得分给最近测速成功的节点排序，最好的在前
+///
+/// 收藏的节点排在前面，黑名单中的节点和不在指定地区的节点不参与。
+pub async fn rank_endpoints(app: &AppHandle, settings: &Settings) -> Result<Vec<Candidate>> {
+  let eps = {
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+
+    Endpoint::select()
+      .where_bind("outcome = ?", TestOutcome::Ok.to_string())
+      .where_("NOT IFNULL(disabled, 0)")
+      .fetch_all(db)
+      .await?
+  };
+
+  let flags: HashMap<_, _> = query_endpoint_settings(app)
+    .await?
+    .into_iter()
+    .map(|s| {
+      let flags = (
+        s.favorite.unwrap_or_default(),
+        s.blacklisted.unwrap_or_default(),
+      );
+      ((s.host, s.port), flags)
+    })
+    .collect();
+
+  let selection = &settings.selection;
+  let failures = latency_stats_by_endpoint(app, selection.failure_window.max(1)).await?;
+
+  // 按最近 N 个样本的中位数计算延迟，避免偶然一次的好成绩
+  let medians = if settings.ep_select_window > 0 {
+    Some(latency_stats_by_endpoint(app, settings.ep_select_window).await?)
+  } else {
+    None
+  };
+
+  let mut ranked: Vec<_> = eps
+    .into_iter()
+    .filter_map(|ep| {
+      let key = (ep.host.clone(), ep.port);
+      let (favorite, blacklisted) = flags.get(&key).copied().unwrap_or_default();
+
+      if blacklisted {
+        return None;
+      }
+
+      if !settings.ep_select_regions.is_empty()
+        && !ep
+          .region
+          .as_ref()
+          .is_some_and(|region| settings.ep_select_regions.contains(region))
+      {
+        return None;
+      }
+
+      let latency = match &medians {
+        Some(medians) => medians.get(&key)?.median?,
+        None => ep.latency?,
+      };
+      let failed = failures
+        .get(&key)
+        .map(|stats| (stats.loss * stats.samples as f64).round())
+        .unwrap_or_default();
+      let score = score(&ep, latency, failed, selection);
+      debug!("Endpoint {} score {:.1}", ep.id, score);
+
+      Some(Candidate {
+        ep,
+        score,
+        favorite,
+      })
+    })
+    .collect();
+
+  sort_candidates(&mut ranked);
+  Ok(ranked)
+}
+
+/// 计算得分；failed 为最近失败的次数
+fn score(ep: &Endpoint, latency: i32, failed: f64, selection: &Selection) -> f64 {
+  let speed = ep.speed.unwrap_or_default() as f64 / 1024.0 / 1024.0;
+
+  latency as f64
+    + ep.jitter.unwrap_or_default() as f64 * selection.jitter_weight
+    + ep.loss.unwrap_or_default() * selection.loss_penalty as f64
+    + failed * selection.failure_penalty as f64
+    - speed * selection.speed_bonus as f64
+}
+
+/// 收藏的在前，其次按得分从小到大
+fn sort_candidates(candidates: &mut [Candidate]) {
+  candidates.sort_by(|a, b| {
+    b.favorite
+      .cmp(&a.favorite)
+      .then(a.score.total_cmp(&b.score))
+  });
+}
+
+/// 是否应该继续使用当前节点
+///
+/// 当前节点仍然可用时，刚切换过或者新节点好得不够多，都不切换。
+pub async fn keep_current(
+  app: &AppHandle,
+  settings: &Settings,
+  ranked: &[Candidate],
+) -> Option<i64> {
+  let (current_id, switched_at) = {
+    let state: State<XrayState> = app.state();
+    let xray_guard = state.xray.lock().await;
+    let current_id = xray_guard.as_ref().map(|xray| xray.endpoint().id)?;
+    let switched_at = *state.switched_at.lock().await;
+    (current_id, switched_at)
+  };
+
+  let current = ranked.iter().find(|c| c.ep.id == current_id)?;
+  let best = ranked.first()?;
+
+  should_keep(current, best, switched_at, &settings.selection).then_some(current_id)
+}
+
+/// 比较当前节点和排名第一的节点，判断是否继续使用当前节点
+fn should_keep(
+  current: &Candidate,
+  best: &Candidate,
+  switched_at: Option<Instant>,
+  selection: &Selection,
+) -> bool {
+  if best.ep.id == current.ep.id {
+    return true;
+  }
+
+  let dwell = Duration::from_secs(selection.min_dwell as u64 * 60);
+
+  if switched_at.is_some_and(|at| at.elapsed() < dwell) {
+    info!("Keeping endpoint {} within min dwell time", current.ep.id);
+    return true;
+  }
+
+  let threshold = current.score * (1.0 - selection.switch_threshold as f64 / 100.0);
+
+  // 从普通节点换到收藏的节点时不看得分
+  if best.favorite == current.favorite && best.score >= threshold {
+    info!(
+      "Keeping endpoint {} ({:.1}), {} ({:.1}) is not better enough",
+      current.ep.id, current.score, best.ep.id, best.score
+    );
+    return true;
+  }
+
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn candidate(id: i64, score: f64, favorite: bool) -> Candidate {
+    Candidate {
+      ep: Endpoint {
+        id,
+        ..Default::default()
+      },
+      score,
+      favorite,
+    }
+  }
+
+  #[test]
+  fn score_adds_penalties_and_speed_bonus() {
+    let selection = Selection {
+      speed_bonus: 10,
+      ..Default::default()
+    };
+    let ep = Endpoint {
+      jitter: Some(20),
+      loss: Some(0.1),
+      speed: Some(2 * 1024 * 1024),
+      ..Default::default()
+    };
+
+    // 100 + 20 * 1.0 + 0.1 * 1000 + 1 * 200 - 2 * 10
+    assert!((score(&ep, 100, 1.0, &selection) - 400.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn better_scores_sort_first() {
+    let mut candidates = vec![
+      candidate(1, 300.0, false),
+      candidate(2, 50.0, false),
+      candidate(3, 100.0, false),
+    ];
+    sort_candidates(&mut candidates);
+
+    let ids: Vec<_> = candidates.iter().map(|c| c.ep.id).collect();
+    assert_eq!(ids, [2, 3, 1]);
+  }
+
+  #[test]
+  fn favorites_sort_before_better_scores() {
+    let mut candidates = vec![
+      candidate(1, 50.0, false),
+      candidate(2, 300.0, true),
+      candidate(3, 100.0, true),
+    ];
+    sort_candidates(&mut candidates);
+
+    let ids: Vec<_> = candidates.iter().map(|c| c.ep.id).collect();
+    assert_eq!(ids, [3, 2, 1]);
+  }
+
+  #[test]
+  fn keeps_current_unless_much_better() {
+    let selection = Selection::default();
+    let current = candidate(1, 100.0, false);
+
+    assert!(should_keep(&current, &current, None, &selection));
+    assert!(should_keep(
+      &current,
+      &candidate(2, 85.0, false),
+      None,
+      &selection
+    ));
+    assert!(!should_keep(
+      &current,
+      &candidate(2, 70.0, false),
+      None,
+      &selection
+    ));
+  }
+
+  #[test]
+  fn keeps_current_within_dwell_time() {
+    let selection = Selection::default();
+    let current = candidate(1, 100.0, false);
+    let best = candidate(2, 10.0, false);
+
+    assert!(should_keep(
+      &current,
+      &best,
+      Some(Instant::now()),
+      &selection
+    ));
+  }
+
+  #[test]
+  fn switches_to_favorite_regardless_of_score() {
+    let selection = Selection::default();
+    let current = candidate(1, 100.0, false);
+
+    assert!(!should_keep(
+      &current,
+      &candidate(2, 95.0, true),
+      None,
+      &selection
+    ));
+  }
+}
@@ -1,11 +1,17 @@
 use log::{error, info};
-use ormlite::{Executor, TableMeta};
+use ormlite::{
+  model::{HasModelBuilder, ModelBuilder},
+  Executor, TableMeta,
+};
 use tauri::{AppHandle, Manager, State};
 use tokio::task::JoinSet;
 
 use crate::{
-  command::endpoint::select_fastest_endpoint,
-  db::{db_query_subscriptions, endpoint::Endpoint, select, subscription::Subscription, DbState},
+  command::endpoint::{rebind_current_endpoint, select_fastest_endpoint},
+  db::{
+    db_query_subscriptions, endpoint::Endpoint, notify_change, select, subscription::Subscription,
+    DbState,
+  },
   error::Result,
 };
 
@@ -37,9 +43,11 @@ pub async fn update_subscriptions(app: AppHandle) -> Result<()> {
     }
   }
 
-  while let Some(_) = set.join_next().await {}
+  while set.join_next().await.is_some() {}
   info!("All subscriptions updated");
 
+  // 锁定时保留当前节点，否则只在明显更好时才切换
+  rebind_current_endpoint(&app).await?;
   select_fastest_endpoint(app).await?;
 
   Ok(())
@@ -55,5 +63,33 @@ pub async fn update_subscription(app: AppHandle, sub_id: i64) -> Result<()> {
   sub.update().await?;
 
   info!("Subscription {} updated", sub_id);
+  rebind_current_endpoint(&app).await?;
   Ok(())
 }
+
+/// 导入 xray 出站对象或完整配置，作为本地订阅保存
+#[tauri::command]
+#[specta::specta]
+pub async fn import_outbounds(app: AppHandle, name: String, json: String) -> Result<i64> {
+  // 先校验一遍，不合法的不保存
+  let eps = Endpoint::from_json(&json)?;
+  info!("Importing {} outbounds as {}", eps.len(), &name);
+
+  let sub = {
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+
+    Subscription::builder()
+      .name(name)
+      .url(String::default())
+      .content(Some(json))
+      .insert(db)
+      .await?
+  };
+
+  notify_change::<Subscription>(&app)?;
+  sub.update().await?;
+
+  Ok(sub.id)
+}
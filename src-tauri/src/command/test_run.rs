@@ -0,0 +1,215 @@
+use std::{
+  collections::HashMap,
+  future::Future,
+  sync::{
+    atomic::{AtomicU32, AtomicU64, Ordering},
+    LazyLock, Mutex,
+  },
+  time::Instant,
+};
+
+use log::{info, warn};
+use ormlite::{Executor, TableMeta};
+use scopeguard::defer;
+use serde::Serialize;
+use specta::Type;
+use tauri::{
+  async_runtime::{spawn, JoinHandle},
+  AppHandle, Manager, State,
+};
+use tokio::sync::watch;
+
+use crate::{
+  db::{endpoint::Endpoint, latency::TestOutcome, notify_change, DbState},
+  error::Result,
+};
+
+/// 正在进行的测速
+struct TestRun {
+  /// 测速任务
+  handle: JoinHandle<()>,
+  /// 完成时为 true；任务被取消时发送端被丢弃
+  done: watch::Receiver<bool>,
+  /// 是否为全部节点的测速
+  full: bool,
+}
+
+static RUNS: LazyLock<Mutex<HashMap<u64, TestRun>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 测速进度，通过 app://test/progress 事件发送
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct TestProgress {
+  /// 测速 ID
+  pub run_id: u64,
+  /// 已测试的节点数
+  pub tested: u32,
+  /// 节点总数
+  pub total: u32,
+  /// 预计剩余时间，秒
+  pub eta: Option<u32>,
+  /// 是否已结束
+  pub done: bool,
+}
+
+/// 测速进度计数
+pub struct ProgressCounter {
+  app: AppHandle,
+  run_id: u64,
+  total: u32,
+  tested: AtomicU32,
+  started: Instant,
+}
+
+impl ProgressCounter {
+  /// 创建计数并发送初始进度
+  pub fn new(app: &AppHandle, run_id: u64, total: usize) -> Self {
+    let counter = Self {
+      app: app.clone(),
+      run_id,
+      total: total as u32,
+      tested: AtomicU32::new(0),
+      started: Instant::now(),
+    };
+    counter.emit(0, false);
+    counter
+  }
+
+  /// 一个节点测试完成
+  pub fn tick(&self) {
+    let tested = self.tested.fetch_add(1, Ordering::SeqCst) + 1;
+    self.emit(tested, false);
+  }
+
+  fn emit(&self, tested: u32, done: bool) {
+    let eta = if tested > 0 && !done {
+      let remaining = self.total.saturating_sub(tested) as f64;
+      Some((self.started.elapsed().as_secs_f64() / tested as f64 * remaining) as u32)
+    } else {
+      None
+    };
+
+    let progress = TestProgress {
+      run_id: self.run_id,
+      tested,
+      total: self.total,
+      eta,
+      done,
+    };
+
+    if let Err(e) = self.app.emit_all("app://test/progress", &progress) {
+      warn!("Failed to emit test progress: {:?}", e);
+    }
+  }
+}
+
+impl Drop for ProgressCounter {
+  /// 测速结束或被取消时发送最终进度
+  fn drop(&mut self) {
+    self.emit(self.tested.load(Ordering::SeqCst), true);
+  }
+}
+
+/// 启动测速并等待完成，返回测速 ID；测速被取消时为空
+///
+/// 全部节点的测速同时只有一个，已有时等待它完成，不再重复测试。
+pub async fn run_test<F, Fut>(full: bool, f: F) -> Option<u64>
+where
+  F: FnOnce(u64) -> Fut,
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  let (run_id, mut done) = {
+    let mut runs = RUNS.lock().unwrap();
+    let existing = runs
+      .iter()
+      .find(|(_, run)| full && run.full)
+      .map(|(id, run)| (*id, run.done.clone()));
+
+    if let Some((run_id, done)) = existing {
+      info!("Joining test run {}", run_id);
+      (run_id, done)
+    } else {
+      let run_id = NEXT_RUN_ID.fetch_add(1, Ordering::SeqCst);
+      let (tx, rx) = watch::channel(false);
+      let fut = f(run_id);
+
+      // 持有锁时创建任务，任务结束时才能删除记录
+      let handle = spawn(async move {
+        defer! {
+          RUNS.lock().unwrap().remove(&run_id);
+        }
+
+        fut.await;
+        let _ = tx.send(true);
+      });
+
+      info!("Started test run {}", run_id);
+      runs.insert(
+        run_id,
+        TestRun {
+          handle,
+          done: rx.clone(),
+          full,
+        },
+      );
+      (run_id, rx)
+    }
+  };
+
+  if done.wait_for(|done| *done).await.is_err() {
+    info!("Test run {} cancelled", run_id);
+    return None;
+  }
+
+  Some(run_id)
+}
+
+/// 取消测速；不指定 ID 时取消全部
+#[tauri::command]
+#[specta::specta]
+pub async fn cancel_test_run(app: AppHandle, run_id: Option<u64>) -> Result<()> {
+  let remaining = {
+    let mut runs = RUNS.lock().unwrap();
+    let ids: Vec<u64> = match run_id {
+      Some(run_id) => vec![run_id],
+      None => runs.keys().copied().collect(),
+    };
+
+    for id in ids {
+      // 任务被丢弃时，其中的 xray 进程随之结束
+      if let Some(run) = runs.remove(&id) {
+        info!("Cancelling test run {}", id);
+        run.handle.abort();
+      }
+    }
+
+    runs.len()
+  };
+
+  if remaining == 0 {
+    // 未测完的节点保留着测试前的延迟，据此恢复结果；失败的原因无法恢复
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+    let sql = format!(
+      "UPDATE {} SET outcome = CASE WHEN latency IS NULL THEN NULL ELSE '{}' END WHERE outcome = '{}'",
+      Endpoint::table_name(),
+      TestOutcome::Ok,
+      TestOutcome::Testing,
+    );
+    db.execute(sql.as_str()).await?;
+    notify_change::<Endpoint>(&app)?;
+  }
+
+  Ok(())
+}
+
+/// 获取正在进行的测速 ID 列表
+#[tauri::command]
+#[specta::specta]
+pub fn get_test_run_ids() -> Vec<u64> {
+  let runs = RUNS.lock().unwrap();
+  runs.keys().copied().collect()
+}
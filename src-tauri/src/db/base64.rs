@@ -1,7 +1,7 @@
 use std::string::FromUtf8Error;
 
 use base64::{
-  alphabet::STANDARD,
+  alphabet::{STANDARD, URL_SAFE},
   engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
   Engine,
 };
@@ -12,6 +12,8 @@ const MAY_PAD: GeneralPurposeConfig =
 
 pub const BASE64_STANDARD_MAY_PAD: GeneralPurpose = GeneralPurpose::new(&STANDARD, MAY_PAD);
 
+pub const BASE64_URL_SAFE_MAY_PAD: GeneralPurpose = GeneralPurpose::new(&URL_SAFE, MAY_PAD);
+
 pub(crate) fn try_base64_decode(s: String) -> Result<String, FromUtf8Error> {
   // 尝试 base64 解码
   match BASE64_STANDARD_MAY_PAD.decode(&s) {
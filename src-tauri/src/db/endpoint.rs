@@ -10,12 +10,12 @@ use specta::Type;
 use thiserror::Error;
 use url::Url;
 
-use crate::db::base64::BASE64_STANDARD_MAY_PAD;
+use crate::db::base64::{BASE64_STANDARD_MAY_PAD, BASE64_URL_SAFE_MAY_PAD};
 
 use super::base64::try_base64_decode;
 
 /// 节点
-#[derive(Clone, Debug, Deserialize, Serialize, Type, Model)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Type, Model)]
 #[serde(rename_all = "camelCase")]
 pub struct Endpoint {
   /// 节点 ID
@@ -31,10 +31,64 @@ pub struct Endpoint {
   pub host: String,
   /// 端口
   pub port: u16,
-  /// 延迟，毫秒；-1 表示正在测试
+  /// 延迟，毫秒；只有测速成功时有值
   pub latency: Option<i32>,
   /// 出站对象
   pub outbound: String,
+  /// 是否禁用；不支持的协议只保留记录，不参与测速
+  pub disabled: Option<bool>,
+  /// 禁用原因
+  pub disabled_reason: Option<String>,
+  /// 延迟抖动，毫秒
+  pub jitter: Option<i32>,
+  /// 丢包率，0 到 1
+  pub loss: Option<f64>,
+  /// 最近一次测速的结果，见 [`TestOutcome`](super::latency::TestOutcome)
+  pub outcome: Option<String>,
+  /// 持续下载速度，字节每秒
+  pub speed: Option<i64>,
+  /// 地区，大写的两位代码，如 JP
+  pub region: Option<String>,
+}
+
+/// 节点详情，由出站对象解析而来
+#[derive(Clone, Debug, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointDetails {
+  /// 节点 ID
+  pub id: i64,
+  /// 协议
+  pub protocol: String,
+  /// 传输方式
+  pub transport: String,
+  /// 传输层安全
+  pub security: String,
+  /// SNI
+  pub sni: Option<String>,
+  /// uTLS 指纹
+  pub fingerprint: Option<String>,
+  /// 流控
+  pub flow: Option<String>,
+  /// 路径或 gRPC 服务名
+  pub path: Option<String>,
+  /// Host 头
+  pub host_header: Option<String>,
+  /// 用户 ID 或密码，已打码
+  pub credential: Option<String>,
+}
+
+/// 节点搜索条件，为空的条件不参与匹配
+#[derive(Clone, Debug, Default, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointFilter {
+  /// 协议
+  pub protocol: Option<String>,
+  /// 传输方式
+  pub transport: Option<String>,
+  /// 传输层安全
+  pub security: Option<String>,
+  /// 在名称、地址、SNI 和 Host 头中查找的文本
+  pub text: Option<String>,
 }
 
 #[derive(Debug, Error)]
@@ -51,6 +105,8 @@ pub enum ParseEndpointError {
   FromUtf8Error(#[from] std::string::FromUtf8Error),
   #[error("unsupported protocol")]
   UnsupportedProtocol,
+  #[error("invalid outbound: {0}")]
+  InvalidOutbound(&'static str),
 }
 
 /// VMess 协议参数
@@ -144,6 +200,7 @@ impl Endpoint {
       port: params.port,
       latency: None,
       outbound: outbound.to_string(),
+      ..Default::default()
     })
   }
 
@@ -158,6 +215,7 @@ impl Endpoint {
       port: uri.port().unwrap_or_default(),
       latency: None,
       outbound: String::default(),
+      ..Default::default()
     })
   }
 
@@ -253,12 +311,271 @@ impl Endpoint {
     ep.outbound = outbound.to_string();
     Ok(ep)
   }
+
+  /// 从 ssr URI 构建节点结构，仅保留名称和地址
+  fn from_ssr(s: &str) -> Result<Self, ParseEndpointError> {
+    let decoded = BASE64_URL_SAFE_MAY_PAD.decode(&s[6..])?;
+    let decoded = String::from_utf8(decoded)?;
+    let (main, query) = decoded.split_once("/?").unwrap_or((decoded.as_str(), ""));
+
+    // host:port:protocol:method:obfs:password，host 可能是 IPv6
+    let mut parts = main.rsplitn(6, ':');
+    let port = parts.nth(4).unwrap_or_default().parse()?;
+    let host = parts.next().unwrap_or_default();
+
+    if host.is_empty() {
+      return Err(ParseEndpointError::UnsupportedProtocol);
+    }
+
+    let remarks = url::form_urlencoded::parse(query.as_bytes())
+      .find(|(k, _)| k == "remarks")
+      .map(|(_, v)| v.into_owned())
+      .unwrap_or_default();
+    let name = BASE64_URL_SAFE_MAY_PAD
+      .decode(&remarks)
+      .ok()
+      .and_then(|v| String::from_utf8(v).ok())
+      .filter(|name| !name.is_empty())
+      .unwrap_or_else(|| format!("{}:{}", host, port));
+
+    Ok(Endpoint {
+      id: 0,
+      sub_id: 0,
+      uri: String::from(s),
+      name,
+      host: String::from(host),
+      port,
+      latency: None,
+      outbound: String::default(),
+      disabled: Some(true),
+      disabled_reason: Some(String::from("Unsupported protocol ssr")),
+      ..Default::default()
+    })
+  }
+
+  /// 从暂不支持的 URI 构建节点结构，仅保留名称和地址
+  fn from_unsupported(s: &str, uri: &Url) -> Result<Self, ParseEndpointError> {
+    if uri.host_str().unwrap_or_default().is_empty() {
+      return Err(ParseEndpointError::UnsupportedProtocol);
+    }
+
+    let mut ep = Self::from_others(s, uri)?;
+    ep.disabled = Some(true);
+    ep.disabled_reason = Some(format!("Unsupported protocol {}", uri.scheme()));
+    Ok(ep)
+  }
+
+  /// 出站对象，叠加用户修改
+  pub fn outbound_with_patch(&self, patch: Option<&str>) -> serde_json::Result<Value> {
+    let mut outbound: Value = serde_json::from_str(&self.outbound)?;
+
+    if let Some(patch) = patch {
+      json_merge(&mut outbound, serde_json::from_str(patch)?);
+    }
+
+    Ok(outbound)
+  }
+
+  /// 从出站对象解析节点详情
+  pub fn details(&self, patch: Option<&str>) -> EndpointDetails {
+    let outbound = self.outbound_with_patch(patch).unwrap_or_default();
+    let settings = &outbound["settings"];
+    let server = if settings["vnext"].is_array() {
+      &settings["vnext"][0]
+    } else if settings["servers"].is_array() {
+      &settings["servers"][0]
+    } else {
+      settings
+    };
+    let user = if server["users"].is_array() {
+      &server["users"][0]
+    } else {
+      server
+    };
+
+    let stream = &outbound["streamSettings"];
+    let transport = stream["network"].as_str().unwrap_or("tcp");
+    let security = stream["security"].as_str().unwrap_or("none");
+    let tls = if security == "reality" {
+      &stream["realitySettings"]
+    } else {
+      &stream["tlsSettings"]
+    };
+    let null = Value::Null;
+    let transport_settings = match transport {
+      "ws" => &stream["wsSettings"],
+      "http" | "h2" => &stream["httpSettings"],
+      "grpc" => &stream["grpcSettings"],
+      "httpupgrade" => &stream["httpupgradeSettings"],
+      "splithttp" | "xhttp" => &stream["xhttpSettings"],
+      "tcp" | "raw" => &stream["tcpSettings"]["header"]["request"],
+      _ => &null,
+    };
+
+    let protocol = match outbound["protocol"].as_str() {
+      Some(protocol) => String::from(protocol),
+      None => String::from(self.uri.split("://").next().unwrap_or_default()),
+    };
+
+    EndpointDetails {
+      id: self.id,
+      protocol,
+      transport: String::from(transport),
+      security: String::from(security),
+      sni: non_empty(&tls["serverName"]),
+      fingerprint: non_empty(&tls["fingerprint"]),
+      flow: non_empty(&user["flow"]),
+      path: non_empty(&transport_settings["path"])
+        .or_else(|| non_empty(&transport_settings["serviceName"])),
+      host_header: non_empty(&transport_settings["host"])
+        .or_else(|| non_empty(&transport_settings["headers"]["host"]))
+        .or_else(|| non_empty(&transport_settings["headers"]["Host"])),
+      credential: non_empty(&user["id"])
+        .or_else(|| non_empty(&user["password"]))
+        .or_else(|| non_empty(&user["pass"]))
+        .map(|credential| mask(&credential)),
+    }
+  }
+
+  /// 判断是否为 JSON 格式的出站对象或配置
+  fn is_json(s: &str) -> bool {
+    let s = s.trim_start();
+    s.starts_with('{') || s.starts_with('[')
+  }
+
+  /// 从 xray 出站对象、出站对象数组或完整配置构建节点结构
+  pub fn from_json(s: &str) -> Result<Vec<Self>, ParseEndpointError> {
+    let value: Value = serde_json::from_str(s)?;
+    let from_config = value.get("outbounds").is_some();
+    let outbounds = match value {
+      Value::Object(mut obj) if from_config => match obj.remove("outbounds") {
+        Some(Value::Array(items)) => items,
+        _ => {
+          return Err(ParseEndpointError::InvalidOutbound(
+            "outbounds is not an array",
+          ))
+        }
+      },
+      Value::Array(items) => items,
+      other => vec![other],
+    };
+
+    let mut eps = Vec::new();
+
+    for outbound in outbounds {
+      let protocol = outbound
+        .get("protocol")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+      // 完整配置中的直连、阻断等出站直接跳过
+      if from_config && NON_PROXY_PROTOCOLS.contains(&protocol) {
+        continue;
+      }
+
+      eps.push(Self::from_outbound(outbound)?);
+    }
+
+    if eps.is_empty() {
+      return Err(ParseEndpointError::InvalidOutbound("no proxy outbound"));
+    }
+
+    Ok(eps)
+  }
+
+  /// 从单个 xray 出站对象构建节点结构
+  fn from_outbound(outbound: Value) -> Result<Self, ParseEndpointError> {
+    if !outbound.is_object() {
+      return Err(ParseEndpointError::InvalidOutbound("not an object"));
+    }
+
+    let protocol = outbound
+      .get("protocol")
+      .and_then(Value::as_str)
+      .ok_or(ParseEndpointError::InvalidOutbound("missing protocol"))?;
+
+    if NON_PROXY_PROTOCOLS.contains(&protocol) {
+      return Err(ParseEndpointError::InvalidOutbound("not a proxy protocol"));
+    }
+
+    let (host, port) = outbound_server(&outbound).ok_or(ParseEndpointError::InvalidOutbound(
+      "missing server address",
+    ))?;
+    let name = match outbound.get("tag").and_then(Value::as_str) {
+      Some(tag) if !tag.is_empty() && tag != "proxy" => String::from(tag),
+      _ => format!("{}:{}", host, port),
+    };
+    let outbound = outbound.to_string();
+
+    Ok(Endpoint {
+      id: 0,
+      sub_id: 0,
+      uri: outbound.clone(),
+      name,
+      host,
+      port,
+      latency: None,
+      outbound,
+      ..Default::default()
+    })
+  }
+
+  /// 解析订阅内容，可以是 JSON 或按行分割的分享链接
+  pub fn parse_all(body: &str) -> Vec<Result<Self, ParseEndpointError>> {
+    if Self::is_json(body) {
+      match Self::from_json(body) {
+        Ok(eps) => eps.into_iter().map(Ok).collect(),
+        Err(e) => vec![Err(e)],
+      }
+    } else {
+      body
+        .split('\n')
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+          debug!("Line: {}", line);
+          Self::from_str(line)
+        })
+        .collect()
+    }
+  }
+}
+
+/// 不是代理的出站协议
+const NON_PROXY_PROTOCOLS: [&str; 4] = ["freedom", "blackhole", "dns", "loopback"];
+
+/// 从出站对象中取得服务器地址和端口
+fn outbound_server(outbound: &Value) -> Option<(String, u16)> {
+  let settings = outbound.get("settings")?;
+  let server = settings
+    .get("vnext")
+    .or_else(|| settings.get("servers"))
+    .and_then(|servers| servers.get(0))
+    .unwrap_or(settings);
+
+  if let (Some(address), Some(port)) = (
+    server.get("address").and_then(Value::as_str),
+    server.get("port").and_then(Value::as_u64),
+  ) {
+    return Some((String::from(address), u16::try_from(port).ok()?));
+  }
+
+  // wireguard
+  let endpoint = settings.get("peers")?.get(0)?.get("endpoint")?.as_str()?;
+  let (address, port) = endpoint.rsplit_once(':')?;
+  let address = address.trim_start_matches('[').trim_end_matches(']');
+  Some((String::from(address), port.parse().ok()?))
 }
 
 impl FromStr for Endpoint {
   type Err = ParseEndpointError;
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
+    // ssr 的 base64 内容不一定是合法的 URL
+    if s.starts_with("ssr://") {
+      return Self::from_ssr(s);
+    }
+
     let uri = Url::parse(s)?;
     debug!("URL: {:?}", &uri);
 
@@ -267,7 +584,7 @@ impl FromStr for Endpoint {
       "trojan" => Self::from_trojan(s, &uri),
       "vless" => Self::from_vless(s, &uri),
       "ss" => Self::from_ss(s, &uri),
-      _ => Err(ParseEndpointError::UnsupportedProtocol),
+      _ => Self::from_unsupported(s, &uri),
     }
   }
 }
@@ -352,6 +669,11 @@ fn uri_to_stream_settings(uri: &Url) -> Value {
     _ => json!({}),
   };
 
+  let allow_insecure = matches!(
+    params.get("allowInsecure").map(String::as_str),
+    Some("1") | Some("true")
+  );
+
   let security = match security.as_str() {
     "tls" => json!({
       "security": "tls",
@@ -359,6 +681,7 @@ fn uri_to_stream_settings(uri: &Url) -> Value {
         "serverName": params.get("sni").unwrap_or(&String::from(uri.host_str().unwrap())),
         "alpn": alpn,
         "fingerprint": params.get("fp").unwrap_or(&String::default()),
+        "allowInsecure": allow_insecure,
       }
     }),
 
@@ -381,8 +704,62 @@ fn uri_to_stream_settings(uri: &Url) -> Value {
   sso
 }
 
+impl EndpointFilter {
+  /// 检查节点是否符合条件
+  pub fn matches(&self, ep: &Endpoint, details: &EndpointDetails) -> bool {
+    let same = |filter: &Option<String>, value: &str| match filter {
+      Some(filter) if !filter.is_empty() => filter.eq_ignore_ascii_case(value),
+      _ => true,
+    };
+
+    if !same(&self.protocol, &details.protocol)
+      || !same(&self.transport, &details.transport)
+      || !same(&self.security, &details.security)
+    {
+      return false;
+    }
+
+    match &self.text {
+      Some(text) if !text.is_empty() => {
+        let text = text.to_lowercase();
+        [
+          Some(&ep.name),
+          Some(&ep.host),
+          details.sni.as_ref(),
+          details.host_header.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|value| value.to_lowercase().contains(&text))
+      }
+      _ => true,
+    }
+  }
+}
+
+/// 取得非空字符串；数组取第一个元素
+fn non_empty(value: &Value) -> Option<String> {
+  let value = if value.is_array() { &value[0] } else { value };
+
+  match value.as_str() {
+    Some(s) if !s.is_empty() => Some(String::from(s)),
+    _ => None,
+  }
+}
+
+/// 给用户 ID 或密码打码，只保留开头几个字符
+fn mask(s: &str) -> String {
+  let visible: String = s.chars().take(4).collect();
+
+  if s.chars().count() > 8 {
+    format!("{}****", visible)
+  } else {
+    String::from("****")
+  }
+}
+
 /// 将第二个对象合并入第一个对象
-fn json_merge(a: &mut Value, b: Value) {
+pub(crate) fn json_merge(a: &mut Value, b: Value) {
   match (a, b) {
     (Value::Object(a), Value::Object(b)) => {
       for (k, v) in b {
@@ -407,3 +784,114 @@ fn to_userinfo(uri: &Url) -> String {
     String::default()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn from_json_single_outbound() {
+    let eps = Endpoint::from_json(
+      r#"{
+        "tag": "Tokyo",
+        "protocol": "vless",
+        "settings": {
+          "vnext": [{ "address": "example.com", "port": 443, "users": [{ "id": "uuid" }] }]
+        }
+      }"#,
+    )
+    .unwrap();
+
+    assert_eq!(eps.len(), 1);
+    assert_eq!(eps[0].name, "Tokyo");
+    assert_eq!(eps[0].host, "example.com");
+    assert_eq!(eps[0].port, 443);
+    assert_eq!(eps[0].uri, eps[0].outbound);
+  }
+
+  #[test]
+  fn from_json_config_skips_non_proxy_outbounds() {
+    let eps = Endpoint::from_json(
+      r#"{
+        "outbounds": [
+          {
+            "tag": "proxy",
+            "protocol": "trojan",
+            "settings": { "servers": [{ "address": "1.2.3.4", "port": 8443 }] }
+          },
+          {
+            "protocol": "wireguard",
+            "settings": { "peers": [{ "endpoint": "[2001:db8::1]:51820" }] }
+          },
+          { "tag": "direct", "protocol": "freedom" },
+          { "tag": "block", "protocol": "blackhole" }
+        ]
+      }"#,
+    )
+    .unwrap();
+
+    assert_eq!(eps.len(), 2);
+    // tag 为 proxy 时用地址作为名称
+    assert_eq!(eps[0].name, "1.2.3.4:8443");
+    assert_eq!(eps[1].host, "2001:db8::1");
+    assert_eq!(eps[1].port, 51820);
+  }
+
+  #[test]
+  fn from_json_rejects_invalid_outbounds() {
+    assert!(Endpoint::from_json(r#"{ "outbounds": [{ "protocol": "freedom" }] }"#).is_err());
+    assert!(Endpoint::from_json(r#"{ "outbounds": {} }"#).is_err());
+    assert!(Endpoint::from_json(r#"{ "protocol": "freedom" }"#).is_err());
+    assert!(Endpoint::from_json(r#"[{ "protocol": "vmess", "settings": {} }]"#).is_err());
+    assert!(Endpoint::from_json("[1]").is_err());
+  }
+
+  #[test]
+  fn parse_all_detects_json() {
+    let results = Endpoint::parse_all(
+      r#"  [{ "protocol": "socks", "settings": { "servers": [{ "address": "a.com", "port": 1080 }] } }]"#,
+    );
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].as_ref().unwrap().host, "a.com");
+
+    let results = Endpoint::parse_all("\n  \nnot a link\n");
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+  }
+
+  #[test]
+  fn from_ssr_keeps_name_and_address() {
+    let remarks = BASE64_URL_SAFE_MAY_PAD.encode("香港 01");
+    let body = format!(
+      "hk.example.com:8388:origin:aes-256-cfb:plain:cGFzcw/?remarks={}",
+      remarks
+    );
+    let link = format!("ssr://{}", BASE64_URL_SAFE_MAY_PAD.encode(body));
+    let ep = Endpoint::from_str(&link).unwrap();
+
+    assert_eq!(ep.name, "香港 01");
+    assert_eq!(ep.host, "hk.example.com");
+    assert_eq!(ep.port, 8388);
+    assert_eq!(ep.disabled, Some(true));
+
+    // IPv6 地址中的冒号不影响解析，没有备注时用地址作为名称
+    let body = "2001:db8::1:443:origin:aes-256-cfb:plain:cGFzcw";
+    let link = format!("ssr://{}", BASE64_URL_SAFE_MAY_PAD.encode(body));
+    let ep = Endpoint::from_str(&link).unwrap();
+    assert_eq!(ep.host, "2001:db8::1");
+    assert_eq!(ep.name, "2001:db8::1:443");
+  }
+
+  #[test]
+  fn unsupported_links_are_disabled() {
+    let ep = Endpoint::from_str("hysteria2://pass@example.com:443#HY2").unwrap();
+    assert_eq!(ep.host, "example.com");
+    assert_eq!(ep.disabled, Some(true));
+    assert_eq!(
+      ep.disabled_reason.as_deref(),
+      Some("Unsupported protocol hysteria2")
+    );
+
+    assert!(Endpoint::from_str("mailto:someone").is_err());
+  }
+}
@@ -0,0 +1,132 @@
+use ormlite::{
+  model::{HasModelBuilder, ModelBuilder},
+  types::Json,
+  Model,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::Result;
+
+use super::{
+  endpoint::Endpoint, endpoint_settings::find_endpoint, notify_change, settings::BalancerStrategy,
+  DbState,
+};
+
+/// 分组中的节点，按地址和端口保存，更新订阅后仍然有效
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMember {
+  /// 节点地址
+  pub host: String,
+  /// 节点端口
+  pub port: u16,
+}
+
+/// 节点分组，作为当前节点时对组内节点负载均衡
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointGroup {
+  /// 分组 ID
+  pub id: i64,
+  /// 名称
+  pub name: String,
+  /// 组内节点；第一个为备用节点，都不可用时使用
+  pub members: Vec<GroupMember>,
+  /// 负载均衡策略
+  pub strategy: BalancerStrategy,
+}
+
+#[derive(Debug, Deserialize, Serialize, Model)]
+pub struct EndpointGroupTable {
+  #[ormlite(primary_key)]
+  pub id: i64,
+  pub name: String,
+  pub members: Json<Vec<GroupMember>>,
+  pub strategy: Json<BalancerStrategy>,
+}
+
+impl From<EndpointGroupTable> for EndpointGroup {
+  fn from(value: EndpointGroupTable) -> Self {
+    Self {
+      id: value.id,
+      name: value.name,
+      members: value.members.0,
+      strategy: value.strategy.0,
+    }
+  }
+}
+
+/// 获取分组
+pub async fn get_endpoint_group(app: &AppHandle, id: i64) -> Result<EndpointGroup> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let item = EndpointGroupTable::select()
+    .where_bind("id = ?", id)
+    .fetch_one(db)
+    .await?;
+  Ok(item.into())
+}
+
+/// 查询全部分组
+pub async fn query_endpoint_groups(app: &AppHandle) -> Result<Vec<EndpointGroup>> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let items = EndpointGroupTable::select().fetch_all(db).await?;
+  Ok(items.into_iter().map(EndpointGroup::from).collect())
+}
+
+/// 保存分组；ID 为 0 时插入，返回分组 ID
+pub async fn save_endpoint_group(app: &AppHandle, doc: EndpointGroup) -> Result<i64> {
+  let id = {
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+
+    if doc.id > 0 {
+      EndpointGroupTable {
+        id: doc.id,
+        name: doc.name,
+        members: Json(doc.members),
+        strategy: Json(doc.strategy),
+      }
+      .update_all_fields(db)
+      .await?
+      .id
+    } else {
+      EndpointGroupTable::builder()
+        .name(doc.name)
+        .members(Json(doc.members))
+        .strategy(Json(doc.strategy))
+        .insert(db)
+        .await?
+        .id
+    }
+  };
+
+  notify_change::<EndpointGroupTable>(app)?;
+  Ok(id)
+}
+
+/// 按地址和端口找到分组中现有的节点，跳过已不存在或禁用的
+pub async fn resolve_group_members(
+  app: &AppHandle,
+  group: &EndpointGroup,
+) -> Result<Vec<Endpoint>> {
+  let mut eps = Vec::new();
+
+  for member in &group.members {
+    if let Some(ep) = find_endpoint(app, &member.host, member.port).await? {
+      if !ep.disabled.unwrap_or_default() {
+        eps.push(ep);
+      }
+    }
+  }
+
+  Ok(eps)
+}
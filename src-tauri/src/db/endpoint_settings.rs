@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use ormlite::{
+  model::{HasModelBuilder, ModelBuilder},
+  types::Json,
+  Model,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::{map_anything, Result};
+
+use super::{
+  endpoint::Endpoint,
+  settings::{Fragment, Mux},
+  DbState,
+};
+
+/// 节点的用户设置，按地址和端口保存，更新订阅后仍然有效
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Model)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointSettings {
+  /// 设置 ID
+  #[ormlite(primary_key)]
+  pub id: i64,
+  /// 节点地址
+  pub host: String,
+  /// 节点端口
+  pub port: u16,
+  /// 前置代理地址
+  pub upstream_host: Option<String>,
+  /// 前置代理端口
+  pub upstream_port: Option<u16>,
+  /// 多路复用，为空时使用全局设置
+  pub mux: Option<Json<Mux>>,
+  /// TLS 分片与 UDP 噪声，为空时使用全局设置
+  pub fragment: Option<Json<Fragment>>,
+  /// 用户对出站对象的修改，JSON 对象，合并到导入的出站对象上
+  pub patch: Option<String>,
+  /// 收藏，自动选择时优先使用
+  pub favorite: Option<bool>,
+  /// 黑名单，自动选择时不使用
+  pub blacklisted: Option<bool>,
+}
+
+impl EndpointSettings {
+  /// 前置代理的地址和端口
+  pub fn upstream(&self) -> Option<(String, u16)> {
+    match (&self.upstream_host, self.upstream_port) {
+      (Some(host), Some(port)) => Some((host.clone(), port)),
+      _ => None,
+    }
+  }
+}
+
+/// 获取节点设置
+pub async fn get_endpoint_settings(
+  app: &AppHandle,
+  host: &str,
+  port: u16,
+) -> Result<Option<EndpointSettings>> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let item = EndpointSettings::select()
+    .where_bind("host = ?", host)
+    .where_bind("port = ?", port)
+    .fetch_optional(db)
+    .await?;
+  Ok(item)
+}
+
+/// 获取节点设置，不存在时返回默认值
+pub async fn get_endpoint_settings_or_default(
+  app: &AppHandle,
+  ep: &Endpoint,
+) -> Result<EndpointSettings> {
+  let settings = get_endpoint_settings(app, &ep.host, ep.port).await?;
+
+  Ok(settings.unwrap_or_else(|| EndpointSettings {
+    host: ep.host.clone(),
+    port: ep.port,
+    ..Default::default()
+  }))
+}
+
+/// 保存节点设置
+pub async fn save_endpoint_settings(app: &AppHandle, doc: EndpointSettings) -> Result<()> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  if doc.id > 0 {
+    doc.update_all_fields(db).await?;
+  } else {
+    EndpointSettings::builder()
+      .host(doc.host)
+      .port(doc.port)
+      .upstream_host(doc.upstream_host)
+      .upstream_port(doc.upstream_port)
+      .mux(doc.mux)
+      .fragment(doc.fragment)
+      .patch(doc.patch)
+      .favorite(doc.favorite)
+      .blacklisted(doc.blacklisted)
+      .insert(db)
+      .await?;
+  }
+
+  Ok(())
+}
+
+/// 查询全部节点设置
+pub async fn query_endpoint_settings(app: &AppHandle) -> Result<Vec<EndpointSettings>> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let items = EndpointSettings::select().fetch_all(db).await?;
+  Ok(items)
+}
+
+/// 按地址和端口查找节点
+pub async fn find_endpoint(app: &AppHandle, host: &str, port: u16) -> Result<Option<Endpoint>> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let item = Endpoint::select()
+    .where_bind("host = ?", host)
+    .where_bind("port = ?", port)
+    .fetch_optional(db)
+    .await?;
+  Ok(item)
+}
+
+/// 解析节点的前置代理链路，按拨号顺序返回，第一个为直接前置的节点
+pub async fn resolve_upstreams(app: &AppHandle, ep: &Endpoint) -> Result<Vec<Endpoint>> {
+  let mut seen = HashSet::from([(ep.host.clone(), ep.port)]);
+  let mut upstreams = Vec::new();
+  let mut next = get_endpoint_settings(app, &ep.host, ep.port)
+    .await?
+    .and_then(|s| s.upstream());
+
+  while let Some((host, port)) = next {
+    if !seen.insert((host.clone(), port)) {
+      return Err(map_anything(format!(
+        "Upstream cycle detected at {}:{}",
+        host, port
+      )));
+    }
+
+    let upstream = find_endpoint(app, &host, port)
+      .await?
+      .ok_or_else(|| map_anything(format!("Upstream {}:{} not found", host, port)))?;
+
+    if upstream.disabled.unwrap_or_default() {
+      return Err(map_anything(format!(
+        "Upstream {} is disabled",
+        upstream.name
+      )));
+    }
+
+    next = get_endpoint_settings(app, &host, port)
+      .await?
+      .and_then(|s| s.upstream());
+    upstreams.push(upstream);
+  }
+
+  Ok(upstreams)
+}
@@ -0,0 +1,486 @@
+use std::{collections::HashMap, error::Error as StdError, fmt, io::ErrorKind};
+
+use ormlite::{
+  model::{HasModelBuilder, ModelBuilder},
+  Model, Row, TableMeta,
+};
+use serde::Serialize;
+use specta::Type;
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::Result;
+
+use super::{endpoint::Endpoint, DbState};
+
+/// 测速历史保留的时间，秒
+const HISTORY_SECS: i64 = 7 * 24 * 3600;
+
+/// 测速结果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestOutcome {
+  /// 成功
+  Ok,
+  /// 超时
+  Timeout,
+  /// 连接被拒绝
+  ConnectRefused,
+  /// 域名解析失败
+  Dns,
+  /// TLS 握手失败
+  Tls,
+  /// 测试 URL 返回了非成功的状态码
+  HttpStatus(u16),
+  /// 响应内容不匹配，可能被劫持
+  BodyMismatch,
+  /// xray 启动失败
+  XrayFailed,
+  /// 正在测试
+  Testing,
+  /// 其他错误
+  Other,
+}
+
+/// 单次测速的结果，成功时为延迟，毫秒
+pub type TestResult = std::result::Result<i32, TestOutcome>;
+
+impl TestOutcome {
+  /// 对请求错误分类
+  pub fn from_error(e: &reqwest::Error) -> Self {
+    if e.is_timeout() {
+      return Self::Timeout;
+    }
+
+    if let Some(status) = e.status() {
+      return Self::HttpStatus(status.as_u16());
+    }
+
+    // SOCKS 代理和 TLS 的错误只能从错误链的描述里区分
+    let mut text = String::new();
+    let mut source: Option<&(dyn StdError + 'static)> = Some(e);
+
+    while let Some(err) = source {
+      if let Some(io) = err.downcast_ref::<std::io::Error>() {
+        match io.kind() {
+          ErrorKind::ConnectionRefused => return Self::ConnectRefused,
+          ErrorKind::TimedOut => return Self::Timeout,
+          _ => {}
+        }
+      }
+
+      text.push_str(&err.to_string().to_lowercase());
+      text.push(' ');
+      source = err.source();
+    }
+
+    if ["dns", "resolve", "lookup"]
+      .iter()
+      .any(|s| text.contains(s))
+    {
+      Self::Dns
+    } else if ["tls", "ssl", "certificate", "handshake"]
+      .iter()
+      .any(|s| text.contains(s))
+    {
+      Self::Tls
+    } else if text.contains("refused") {
+      Self::ConnectRefused
+    } else if text.contains("timed out") || text.contains("timeout") {
+      Self::Timeout
+    } else {
+      Self::Other
+    }
+  }
+
+  /// 对直接连接节点时的错误分类
+  pub fn from_io_error(e: &std::io::Error) -> Self {
+    match e.kind() {
+      ErrorKind::ConnectionRefused => Self::ConnectRefused,
+      ErrorKind::TimedOut => Self::Timeout,
+      _ => {
+        let text = e.to_string().to_lowercase();
+
+        if text.contains("lookup") || text.contains("resolve") {
+          Self::Dns
+        } else {
+          Self::Other
+        }
+      }
+    }
+  }
+
+  /// 多次测速的总体结果，有一次成功即为成功，否则取最后一次的错误
+  pub fn from_samples(samples: &[TestResult]) -> Self {
+    if samples.iter().any(|r| r.is_ok()) {
+      return Self::Ok;
+    }
+
+    samples
+      .iter()
+      .rev()
+      .find_map(|r| r.err())
+      .unwrap_or(Self::Other)
+  }
+}
+
+impl fmt::Display for TestOutcome {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Ok => write!(f, "ok"),
+      Self::Timeout => write!(f, "timeout"),
+      Self::ConnectRefused => write!(f, "connectRefused"),
+      Self::Dns => write!(f, "dns"),
+      Self::Tls => write!(f, "tls"),
+      Self::HttpStatus(status) => write!(f, "http:{}", status),
+      Self::BodyMismatch => write!(f, "bodyMismatch"),
+      Self::XrayFailed => write!(f, "xrayFailed"),
+      Self::Testing => write!(f, "testing"),
+      Self::Other => write!(f, "other"),
+    }
+  }
+}
+
+impl From<&str> for TestOutcome {
+  fn from(s: &str) -> Self {
+    match s {
+      "ok" => Self::Ok,
+      "timeout" => Self::Timeout,
+      "connectRefused" => Self::ConnectRefused,
+      "dns" => Self::Dns,
+      "tls" => Self::Tls,
+      "bodyMismatch" => Self::BodyMismatch,
+      "xrayFailed" => Self::XrayFailed,
+      "testing" => Self::Testing,
+      _ => s
+        .strip_prefix("http:")
+        .and_then(|status| status.parse().ok())
+        .map(Self::HttpStatus)
+        .unwrap_or(Self::Other),
+    }
+  }
+}
+
+/// 测速记录，按地址和端口保存，更新订阅后仍然有效
+#[derive(Clone, Debug, Serialize, Type, Model)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyLog {
+  /// 记录 ID
+  #[ormlite(primary_key)]
+  pub id: i64,
+  /// 节点地址
+  pub host: String,
+  /// 节点端口
+  pub port: u16,
+  /// 时间戳，秒
+  pub ts: i64,
+  /// 延迟，毫秒；失败时为空
+  pub latency: Option<i32>,
+  /// 结果，见 [`TestOutcome`]
+  pub outcome: String,
+}
+
+/// 节点访问各站点的测速结果，按地址和端口保存
+#[derive(Clone, Debug, Serialize, Type, Model)]
+#[serde(rename_all = "camelCase")]
+pub struct WebsiteLatency {
+  /// 记录 ID
+  #[ormlite(primary_key)]
+  pub id: i64,
+  /// 节点地址
+  pub host: String,
+  /// 节点端口
+  pub port: u16,
+  /// 站点 ID
+  pub website_id: i64,
+  /// 时间戳，秒
+  pub ts: i64,
+  /// 延迟，毫秒；失败时为空
+  pub latency: Option<i32>,
+  /// 结果，见 [`TestOutcome`]
+  pub outcome: String,
+}
+
+/// 延迟统计
+#[derive(Clone, Debug, Default, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyStats {
+  /// 样本数
+  pub samples: u32,
+  /// 延迟中位数，毫秒；全部失败时为空
+  pub median: Option<i32>,
+  /// 抖动，相邻成功样本之差的平均值，毫秒
+  pub jitter: Option<i32>,
+  /// 丢包率，0 到 1
+  pub loss: f64,
+}
+
+impl LatencyStats {
+  /// 由样本计算统计值，失败的样本为空
+  pub fn from_samples(samples: &[Option<i32>]) -> Self {
+    if samples.is_empty() {
+      return Self::default();
+    }
+
+    let ok: Vec<i32> = samples.iter().flatten().copied().collect();
+
+    let jitter = if ok.len() > 1 {
+      let sum: i64 = ok.windows(2).map(|w| (w[1] - w[0]).abs() as i64).sum();
+      Some((sum / (ok.len() as i64 - 1)) as i32)
+    } else {
+      None
+    };
+
+    let mut sorted = ok.clone();
+    sorted.sort_unstable();
+    let median = match sorted.len() {
+      0 => None,
+      n if n % 2 == 1 => Some(sorted[n / 2]),
+      n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2),
+    };
+
+    Self {
+      samples: samples.len() as u32,
+      median,
+      jitter,
+      loss: (samples.len() - ok.len()) as f64 / samples.len() as f64,
+    }
+  }
+}
+
+/// 当前时间戳，秒
+pub fn now_secs() -> i64 {
+  std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap()
+    .as_secs() as i64
+}
+
+/// 保存一次测速的全部样本
+pub async fn insert_latency_logs(
+  app: &AppHandle,
+  ep: &Endpoint,
+  samples: &[TestResult],
+) -> Result<()> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+  let ts = now_secs();
+
+  for sample in samples {
+    let outcome = match sample {
+      Ok(_) => TestOutcome::Ok,
+      Err(outcome) => *outcome,
+    };
+
+    LatencyLog::builder()
+      .host(ep.host.clone())
+      .port(ep.port)
+      .ts(ts)
+      .latency(sample.ok())
+      .outcome(outcome.to_string())
+      .insert(&mut *db)
+      .await?;
+  }
+
+  Ok(())
+}
+
+/// 删除过期的测速记录
+pub async fn remove_expired_latency_logs(app: &AppHandle) -> Result<()> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let sql = format!("DELETE FROM {} WHERE ts < ?", LatencyLog::table_name());
+  ormlite::query(&sql)
+    .bind(now_secs() - HISTORY_SECS)
+    .fetch_optional(db)
+    .await?;
+
+  Ok(())
+}
+
+/// 查询节点的测速记录，最新的在前
+pub async fn query_latency_logs(
+  app: &AppHandle,
+  ep: &Endpoint,
+  limit: u32,
+) -> Result<Vec<LatencyLog>> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let items = LatencyLog::select()
+    .where_bind("host = ?", ep.host.clone())
+    .where_bind("port = ?", ep.port)
+    .order_desc("id")
+    .limit(limit as usize)
+    .fetch_all(db)
+    .await?;
+  Ok(items)
+}
+
+/// 按节点统计最近 N 次样本
+pub async fn latency_stats_by_endpoint(
+  app: &AppHandle,
+  last_n: u32,
+) -> Result<HashMap<(String, u16), LatencyStats>> {
+  let rows = {
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+
+    // 在数据库中取每个节点最新的 N 条，不用读出全部历史
+    let sql = format!(
+      "SELECT host, port, latency FROM (\
+      SELECT host, port, latency, ROW_NUMBER() OVER (PARTITION BY host, port ORDER BY id DESC) AS n FROM {}\
+      ) WHERE n <= ? ORDER BY host, port, n",
+      LatencyLog::table_name()
+    );
+    ormlite::query(&sql).bind(last_n).fetch_all(db).await?
+  };
+
+  let mut samples: HashMap<(String, u16), Vec<Option<i32>>> = HashMap::new();
+
+  for row in rows {
+    samples
+      .entry((
+        row.try_get::<String, usize>(0)?,
+        row.try_get::<u16, usize>(1)?,
+      ))
+      .or_default()
+      .push(row.try_get::<Option<i32>, usize>(2)?);
+  }
+
+  Ok(
+    samples
+      .into_iter()
+      .map(|(key, samples)| (key, LatencyStats::from_samples(&samples)))
+      .collect(),
+  )
+}
+
+/// 保存节点访问站点的测速结果，覆盖之前的结果
+pub async fn save_website_latency(
+  app: &AppHandle,
+  ep: &Endpoint,
+  website_id: i64,
+  result: TestResult,
+) -> Result<()> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+  let outcome = match result {
+    Ok(_) => TestOutcome::Ok,
+    Err(outcome) => outcome,
+  };
+
+  let sql = format!(
+    "INSERT INTO {} (host, port, website_id, ts, latency, outcome) VALUES (?, ?, ?, ?, ?, ?) \
+    ON CONFLICT (host, port, website_id) DO UPDATE SET ts = excluded.ts, latency = excluded.latency, outcome = excluded.outcome",
+    WebsiteLatency::table_name()
+  );
+  ormlite::query(&sql)
+    .bind(ep.host.clone())
+    .bind(ep.port)
+    .bind(website_id)
+    .bind(now_secs())
+    .bind(result.ok())
+    .bind(outcome.to_string())
+    .fetch_optional(db)
+    .await?;
+
+  Ok(())
+}
+
+/// 查询站点的测速结果；不指定站点时查询全部
+pub async fn query_website_latencies(
+  app: &AppHandle,
+  website_id: Option<i64>,
+) -> Result<Vec<WebsiteLatency>> {
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let query = WebsiteLatency::select();
+  let query = match website_id {
+    Some(website_id) => query.where_bind("website_id = ?", website_id),
+    None => query,
+  };
+  let items = query.fetch_all(db).await?;
+  Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stats_of_empty_samples() {
+    let stats = LatencyStats::from_samples(&[]);
+    assert_eq!(stats.samples, 0);
+    assert_eq!(stats.median, None);
+    assert_eq!(stats.jitter, None);
+    assert_eq!(stats.loss, 0.0);
+  }
+
+  #[test]
+  fn stats_with_odd_and_even_samples() {
+    let stats = LatencyStats::from_samples(&[Some(120), Some(100), Some(140)]);
+    assert_eq!(stats.median, Some(120));
+    // |100 - 120| 和 |140 - 100| 的平均值
+    assert_eq!(stats.jitter, Some(30));
+    assert_eq!(stats.loss, 0.0);
+
+    let stats = LatencyStats::from_samples(&[Some(100), Some(200)]);
+    assert_eq!(stats.median, Some(150));
+  }
+
+  #[test]
+  fn stats_count_failures_as_loss() {
+    let stats = LatencyStats::from_samples(&[None, Some(100), None, Some(110)]);
+    assert_eq!(stats.samples, 4);
+    assert_eq!(stats.median, Some(105));
+    assert_eq!(stats.jitter, Some(10));
+    assert_eq!(stats.loss, 0.5);
+
+    let stats = LatencyStats::from_samples(&[None, None]);
+    assert_eq!(stats.median, None);
+    assert_eq!(stats.loss, 1.0);
+  }
+
+  #[test]
+  fn outcome_round_trips_through_string() {
+    let outcomes = [
+      TestOutcome::Ok,
+      TestOutcome::Timeout,
+      TestOutcome::ConnectRefused,
+      TestOutcome::Dns,
+      TestOutcome::Tls,
+      TestOutcome::HttpStatus(503),
+      TestOutcome::BodyMismatch,
+      TestOutcome::XrayFailed,
+      TestOutcome::Testing,
+      TestOutcome::Other,
+    ];
+
+    for outcome in outcomes {
+      assert_eq!(TestOutcome::from(outcome.to_string().as_str()), outcome);
+    }
+
+    assert_eq!(TestOutcome::from("http:abc"), TestOutcome::Other);
+    assert_eq!(TestOutcome::from("unknown"), TestOutcome::Other);
+  }
+
+  #[test]
+  fn outcome_of_samples() {
+    assert_eq!(
+      TestOutcome::from_samples(&[Err(TestOutcome::Timeout), Ok(100)]),
+      TestOutcome::Ok
+    );
+    assert_eq!(
+      TestOutcome::from_samples(&[Err(TestOutcome::Timeout), Err(TestOutcome::Tls)]),
+      TestOutcome::Tls
+    );
+    assert_eq!(TestOutcome::from_samples(&[]), TestOutcome::Other);
+  }
+}
@@ -1,16 +1,26 @@
 mod base64;
 pub mod endpoint;
+pub mod endpoint_group;
+pub mod endpoint_settings;
 pub mod flow;
+pub mod latency;
 pub mod log;
 pub mod settings;
 pub mod subscription;
 pub mod website;
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use ::log::debug;
-use endpoint::Endpoint;
+use endpoint::{Endpoint, EndpointDetails, EndpointFilter};
+use endpoint_group::{
+  query_endpoint_groups, save_endpoint_group, EndpointGroup, EndpointGroupTable,
+};
+use endpoint_settings::{get_endpoint_settings, query_endpoint_settings, EndpointSettings};
 use flow::Flow;
+use latency::{
+  query_latency_logs, query_website_latencies, LatencyLog, LatencyStats, WebsiteLatency,
+};
 use log::Log;
 use ormlite::{
   model::{HasModelBuilder, ModelBuilder},
@@ -20,14 +30,21 @@ use ormlite::{
   types::Json,
   Connection, Executor, FromRow, Model, Row, TableMeta,
 };
-use settings::{Settings, SettingsTable};
-use subscription::Subscription;
+use settings::{
+  AllowInsecure, Balancer, Fragment, Mux, PingMode, Probe, Selection, Settings, SettingsTable,
+  SpeedTest,
+};
+use subscription::{Subscription, SubscriptionStats};
 use tauri::{async_runtime::Mutex, AppHandle, Manager, State};
 use website::Website;
 
-use crate::{command::endpoint::start_check_current_endpoint, error::Result};
+use crate::{
+  command::endpoint::{leave_current_group, reload_current_group, start_check_current_endpoint},
+  error::{map_anything, Result},
+  region::region_from_name,
+};
 
-const CURRENT_DB_VERSION: u32 = 3;
+const CURRENT_DB_VERSION: u32 = 16;
 
 #[derive(Default)]
 pub struct DbState {
@@ -86,7 +103,7 @@ async fn upgrade_if_needed(db: &mut SqliteConnection) -> Result<()> {
   let version = version.try_get::<u32, usize>(0)?;
   debug!("Current db version {}", version);
 
-  if version < CURRENT_DB_VERSION {
+  if version < 3 {
     let sql = format!("CREATE TABLE IF NOT EXISTS {} ({} INTEGER PRIMARY KEY, name TEXT NOT NULL, url TEXT NOT NULL, disabled INTEGER)", Subscription::table_name(), Subscription::primary_key().unwrap());
     db.execute(sql.as_str()).await?;
 
@@ -117,7 +134,212 @@ async fn upgrade_if_needed(db: &mut SqliteConnection) -> Result<()> {
       Website::primary_key().unwrap(),
     );
     db.execute(sql.as_str()).await?;
+  }
+
+  if version < 4 {
+    // 本地订阅内容
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN content TEXT",
+      Subscription::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 5 {
+    // 不支持的节点
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN disabled INTEGER",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN disabled_reason TEXT",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 6 {
+    // 节点的用户设置
+    let sql = format!(
+      "CREATE TABLE IF NOT EXISTS {} ({} INTEGER PRIMARY KEY, host TEXT NOT NULL, port INTEGER NOT NULL, upstream_host TEXT, upstream_port INTEGER)",
+      EndpointSettings::table_name(),
+      EndpointSettings::primary_key().unwrap(),
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "CREATE UNIQUE INDEX IF NOT EXISTS unique_endpoint_settings ON {} (host, port)",
+      EndpointSettings::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 7 {
+    // 节点的多路复用设置
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN mux TEXT",
+      EndpointSettings::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 8 {
+    // 节点的分片设置
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN fragment TEXT",
+      EndpointSettings::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 9 {
+    // 用户对节点的修改
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN patch TEXT",
+      EndpointSettings::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 10 {
+    // 测速记录
+    let sql = format!(
+      "CREATE TABLE IF NOT EXISTS {} ({} INTEGER PRIMARY KEY, host TEXT NOT NULL, port INTEGER NOT NULL, ts INTEGER NOT NULL, latency INTEGER, outcome TEXT NOT NULL)",
+      LatencyLog::table_name(),
+      LatencyLog::primary_key().unwrap(),
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "CREATE INDEX IF NOT EXISTS latency_log_endpoint ON {} (host, port)",
+      LatencyLog::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN jitter INTEGER",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN loss REAL",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 11 {
+    // 测速结果
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN outcome TEXT",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    // 转换原有的 999999 和 -1
+    let sql = format!(
+      "UPDATE {} SET outcome = CASE WHEN latency >= 999999 THEN 'other' ELSE 'ok' END WHERE latency > 0",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "UPDATE {} SET latency = NULL WHERE latency < 0 OR latency >= 999999",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "UPDATE {} SET outcome = 'other' WHERE outcome = 'failed'",
+      LatencyLog::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 12 {
+    // 下载速度
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN speed INTEGER",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
 
+  if version < 13 {
+    // 节点访问各站点的测速结果
+    let sql = format!(
+      "CREATE TABLE IF NOT EXISTS {} ({} INTEGER PRIMARY KEY, host TEXT NOT NULL, port INTEGER NOT NULL, website_id INTEGER NOT NULL REFERENCES {}(id) ON DELETE CASCADE ON UPDATE CASCADE, ts INTEGER NOT NULL, latency INTEGER, outcome TEXT NOT NULL)",
+      WebsiteLatency::table_name(),
+      WebsiteLatency::primary_key().unwrap(),
+      Website::table_name(),
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "CREATE UNIQUE INDEX IF NOT EXISTS unique_website_latency ON {} (host, port, website_id)",
+      WebsiteLatency::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 14 {
+    // 收藏和黑名单
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN favorite INTEGER",
+      EndpointSettings::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN blacklisted INTEGER",
+      EndpointSettings::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < 15 {
+    // 地区
+    let sql = format!(
+      "ALTER TABLE {} ADD COLUMN region TEXT",
+      Endpoint::table_name()
+    );
+    db.execute(sql.as_str()).await?;
+
+    // 按名称补全已有节点的地区；其余的在启动后查找 geoip
+    let sql = format!("SELECT id, name FROM {}", Endpoint::table_name());
+    let rows = ormlite::query(sql.as_str()).fetch_all(&mut *db).await?;
+    let sql = format!(
+      "UPDATE {} SET region = ? WHERE id = ?",
+      Endpoint::table_name()
+    );
+
+    for row in rows {
+      let name = row.try_get::<String, usize>(1)?;
+
+      if let Some(region) = region_from_name(&name) {
+        ormlite::query(sql.as_str())
+          .bind(region)
+          .bind(row.try_get::<i64, usize>(0)?)
+          .fetch_optional(&mut *db)
+          .await?;
+      }
+    }
+  }
+
+  if version < 16 {
+    // 节点分组
+    let sql = format!(
+      "CREATE TABLE IF NOT EXISTS {} ({} INTEGER PRIMARY KEY, name TEXT NOT NULL, members TEXT NOT NULL, strategy TEXT NOT NULL)",
+      EndpointGroupTable::table_name(),
+      EndpointGroupTable::primary_key().unwrap(),
+    );
+    db.execute(sql.as_str()).await?;
+  }
+
+  if version < CURRENT_DB_VERSION {
     let sql = format!("PRAGMA user_version = {}", CURRENT_DB_VERSION);
     db.execute(sql.as_str()).await?;
   }
@@ -231,7 +453,20 @@ pub async fn db_get_settings(state: State<'_, DbState>) -> Result<Settings> {
     ep_test_interval: 3,
     ep_test_concurrency: 32,
     ep_test_url: String::from("https://www.google.com/generate_204"),
+    probe: Probe::default(),
+    ep_test_samples: 3,
+    ep_select_window: 0,
+    selection: Selection::default(),
+    ep_ping_mode: PingMode::Off,
+    ep_select_regions: Vec::new(),
+    speed_test: SpeedTest::default(),
+    balancer: Balancer::default(),
     rule: String::from("default"),
+    mux: Mux::default(),
+    fragment: Fragment::default(),
+    fingerprint: String::from("chrome"),
+    allow_insecure: AllowInsecure::Link,
+    allow_insecure_hosts: Vec::new(),
   };
 
   let mut db_guard = state.db.lock().await;
@@ -301,6 +536,8 @@ pub async fn insert_flow(app: &AppHandle, doc: Flow) -> Result<()> {
 #[tauri::command]
 #[specta::specta]
 pub async fn db_set_settings(state: State<'_, DbState>, settings: Settings) -> Result<()> {
+  settings.probe.body_regex().map_err(map_anything)?;
+
   let all = query::<SettingsTable>(&state).await?;
   {
     let mut db_guard = state.db.lock().await;
@@ -332,6 +569,7 @@ pub async fn db_insert_subscription(app: AppHandle, doc: Subscription) -> Result
   Subscription::builder()
     .name(doc.name)
     .url(doc.url)
+    .content(doc.content)
     .insert(db)
     .await?;
 
@@ -399,6 +637,89 @@ pub async fn db_count_endpoints(state: State<'_, DbState>) -> Result<u32> {
   count::<Endpoint>(&state).await
 }
 
+/// 获取节点详情
+#[tauri::command]
+#[specta::specta]
+pub async fn db_get_endpoint_details(app: AppHandle, ep_id: i64) -> Result<EndpointDetails> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let settings = get_endpoint_settings(&app, &ep.host, ep.port).await?;
+  let patch = settings.and_then(|s| s.patch);
+  Ok(ep.details(patch.as_deref()))
+}
+
+/// 按条件查找节点详情
+#[tauri::command]
+#[specta::specta]
+pub async fn db_search_endpoint_details(
+  app: AppHandle,
+  filter: EndpointFilter,
+) -> Result<Vec<EndpointDetails>> {
+  let eps = query::<Endpoint>(&app.state()).await?;
+  let patches: HashMap<_, _> = query_endpoint_settings(&app)
+    .await?
+    .into_iter()
+    .filter_map(|s| Some(((s.host, s.port), s.patch?)))
+    .collect();
+  let items = eps
+    .iter()
+    .filter_map(|ep| {
+      let patch = patches.get(&(ep.host.clone(), ep.port));
+      let details = ep.details(patch.map(String::as_str));
+      filter.matches(ep, &details).then_some(details)
+    })
+    .collect();
+  Ok(items)
+}
+
+/// 查询节点的测速记录
+#[tauri::command]
+#[specta::specta]
+pub async fn db_query_latency_logs(
+  app: AppHandle,
+  ep_id: i64,
+  limit: u32,
+) -> Result<Vec<LatencyLog>> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  query_latency_logs(&app, &ep, limit).await
+}
+
+/// 统计节点最近 N 次测速的延迟
+#[tauri::command]
+#[specta::specta]
+pub async fn db_get_latency_stats(app: AppHandle, ep_id: i64, last_n: u32) -> Result<LatencyStats> {
+  let ep: Endpoint = select(&app, ep_id).await?;
+  let logs = query_latency_logs(&app, &ep, last_n).await?;
+  let samples: Vec<_> = logs.into_iter().map(|log| log.latency).collect();
+  Ok(LatencyStats::from_samples(&samples))
+}
+
+/// 按订阅统计节点数量
+#[tauri::command]
+#[specta::specta]
+pub async fn db_count_endpoints_by_subscription(
+  state: State<'_, DbState>,
+) -> Result<Vec<SubscriptionStats>> {
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+
+  let sql = format!(
+    "SELECT sub_id, COUNT(*), COUNT(CASE WHEN disabled THEN 1 END) FROM {} GROUP BY sub_id",
+    Endpoint::table_name()
+  );
+  let rows = ormlite::query(sql.as_str()).fetch_all(db).await?;
+  let mut items = Vec::new();
+
+  for row in rows {
+    items.push(SubscriptionStats {
+      sub_id: row.try_get::<i64, usize>(0)?,
+      total: row.try_get::<u32, usize>(1)?,
+      disabled: row.try_get::<u32, usize>(2)?,
+    });
+  }
+
+  Ok(items)
+}
+
 /// 查询日志
 #[tauri::command]
 #[specta::specta]
@@ -462,3 +783,48 @@ pub async fn db_remove_website(app: AppHandle, id: i64) -> Result<()> {
 pub async fn db_query_websites(state: State<'_, DbState>) -> Result<Vec<Website>> {
   query::<Website>(&state).await
 }
+
+/// 查询节点访问站点的测速结果，按节点地址和端口对应；不指定站点时查询全部
+#[tauri::command]
+#[specta::specta]
+pub async fn db_query_website_latencies(
+  app: AppHandle,
+  website_id: Option<i64>,
+) -> Result<Vec<WebsiteLatency>> {
+  query_website_latencies(&app, website_id).await
+}
+
+/// 查询节点分组
+#[tauri::command]
+#[specta::specta]
+pub async fn db_query_endpoint_groups(app: AppHandle) -> Result<Vec<EndpointGroup>> {
+  query_endpoint_groups(&app).await
+}
+
+/// 插入节点分组，返回分组 ID
+#[tauri::command]
+#[specta::specta]
+pub async fn db_insert_endpoint_group(app: AppHandle, doc: EndpointGroup) -> Result<i64> {
+  save_endpoint_group(&app, EndpointGroup { id: 0, ..doc }).await
+}
+
+/// 更新节点分组
+#[tauri::command]
+#[specta::specta]
+pub async fn db_update_endpoint_group(app: AppHandle, doc: EndpointGroup) -> Result<()> {
+  if doc.id <= 0 {
+    return Err(map_anything("Invalid group ID"));
+  }
+
+  let id = doc.id;
+  save_endpoint_group(&app, doc).await?;
+  reload_current_group(&app, id).await
+}
+
+/// 删除节点分组
+#[tauri::command]
+#[specta::specta]
+pub async fn db_remove_endpoint_group(app: AppHandle, id: i64) -> Result<()> {
+  remove::<EndpointGroupTable>(&app, id).await?;
+  leave_current_group(&app, id).await
+}
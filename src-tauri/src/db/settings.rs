@@ -1,4 +1,5 @@
 use ormlite::{types::Json, Model};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
@@ -20,8 +21,291 @@ pub struct Settings {
   pub ep_test_concurrency: u32,
   /// 测试用 URL
   pub ep_test_url: String,
+  /// 测试请求
+  #[serde(default)]
+  pub probe: Probe,
+  /// 每次测速的采样次数
+  #[serde(default = "default_ep_test_samples")]
+  pub ep_test_samples: u32,
+  /// 选择节点时使用最近多少个样本的中位数；为 0 时只看最近一次测速
+  #[serde(default)]
+  pub ep_select_window: u32,
+  /// 节点选择策略
+  #[serde(default)]
+  pub selection: Selection,
+  /// 自动选择时只使用这些地区的节点，如 JP；为空时不限制
+  #[serde(default)]
+  pub ep_select_regions: Vec<String>,
+  /// 真实延迟测试前的快速预检，不通的节点不再测试
+  #[serde(default)]
+  pub ep_ping_mode: PingMode,
+  /// 下载测速
+  #[serde(default)]
+  pub speed_test: SpeedTest,
+  /// 负载均衡，由 xray 自己检测和切换节点
+  #[serde(default)]
+  pub balancer: Balancer,
   /// 路由规则
   pub rule: String,
+  /// 多路复用
+  #[serde(default)]
+  pub mux: Mux,
+  /// TLS 分片与 UDP 噪声
+  #[serde(default)]
+  pub fragment: Fragment,
+  /// 默认 uTLS 指纹，链接未指定时使用；为空时不设置
+  #[serde(default = "default_fingerprint")]
+  pub fingerprint: String,
+  /// allowInsecure 策略
+  #[serde(default)]
+  pub allow_insecure: AllowInsecure,
+  /// 强制允许不安全连接的主机
+  #[serde(default)]
+  pub allow_insecure_hosts: Vec<String>,
+}
+
+fn default_ep_test_samples() -> u32 {
+  3
+}
+
+fn default_fingerprint() -> String {
+  String::from("chrome")
+}
+
+/// allowInsecure 策略
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum AllowInsecure {
+  /// 按链接设置
+  #[default]
+  Link,
+  /// 全部禁止
+  ForceOff,
+  /// 对列出的主机强制允许，其余按链接设置
+  ForceOnForHosts,
+}
+
+/// 节点选择策略；得分相当于毫秒，越小越好
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Selection {
+  /// 抖动的权重
+  pub jitter_weight: f64,
+  /// 丢包率为 100% 时的罚分
+  pub loss_penalty: u32,
+  /// 最近每失败一次的罚分
+  pub failure_penalty: u32,
+  /// 统计最近多少次测速的失败次数
+  pub failure_window: u32,
+  /// 每 MiB/s 下载速度的加分
+  pub speed_bonus: u32,
+  /// 新节点的得分至少要好多少才切换，百分比
+  pub switch_threshold: u32,
+  /// 切换或手动选择节点后至少保持多久，分钟；当前节点不可用时不受限制
+  pub min_dwell: u32,
+  /// 当前节点不可用时，依次尝试最近测速排名靠前的几个节点
+  #[serde(default = "default_failover_candidates")]
+  pub failover_candidates: u32,
+  /// 当前节点延迟超过多少毫秒算作过慢；为 0 时不检查
+  #[serde(default)]
+  pub slow_latency: u32,
+  /// 连续多少次检查过慢时切换节点
+  #[serde(default = "default_slow_checks")]
+  pub slow_checks: u32,
+}
+
+fn default_failover_candidates() -> u32 {
+  3
+}
+
+fn default_slow_checks() -> u32 {
+  3
+}
+
+impl Default for Selection {
+  fn default() -> Self {
+    Self {
+      jitter_weight: 1.0,
+      loss_penalty: 1000,
+      failure_penalty: 200,
+      failure_window: 10,
+      speed_bonus: 0,
+      switch_threshold: 20,
+      min_dwell: 10,
+      failover_candidates: default_failover_candidates(),
+      slow_latency: 0,
+      slow_checks: default_slow_checks(),
+    }
+  }
+}
+
+/// 测试请求设置
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Probe {
+  /// 请求方法；设置了内容匹配时总是 GET
+  pub method: ProbeMethod,
+  /// 视为成功的状态码；为空时任何 2xx 都算成功
+  pub expected_status: Vec<u16>,
+  /// 响应内容需要匹配的正则表达式；为空时不检查
+  pub body_regex: String,
+  /// 是否跟随重定向
+  pub follow_redirects: bool,
+  /// 超时，秒
+  pub timeout: u32,
+  /// 真实延迟：先请求一次建立连接，只计算第二次请求的时间
+  pub real_delay: bool,
+}
+
+impl Default for Probe {
+  fn default() -> Self {
+    Self {
+      method: ProbeMethod::Head,
+      expected_status: Vec::new(),
+      body_regex: String::new(),
+      follow_redirects: true,
+      timeout: 10,
+      real_delay: false,
+    }
+  }
+}
+
+impl Probe {
+  /// 编译内容匹配的正则表达式；为空时返回 None
+  pub fn body_regex(&self) -> Result<Option<Regex>, regex::Error> {
+    if self.body_regex.is_empty() {
+      return Ok(None);
+    }
+
+    Regex::new(&self.body_regex).map(Some)
+  }
+}
+
+/// 测试请求方法
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum ProbeMethod {
+  #[default]
+  Head,
+  Get,
+}
+
+/// 快速预检方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum PingMode {
+  /// 不预检
+  #[default]
+  Off,
+  /// TCP 连接
+  Tcp,
+  /// TLS 握手，使用节点的 SNI；节点没有 TLS 时只做 TCP 连接
+  Tls,
+}
+
+/// 负载均衡策略
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum BalancerStrategy {
+  /// 延迟最低
+  #[default]
+  LeastPing,
+  /// 延迟最稳定
+  LeastLoad,
+  /// 随机
+  Random,
+  /// 轮流使用
+  RoundRobin,
+}
+
+impl BalancerStrategy {
+  /// xray 配置中的名称
+  pub fn as_xray(&self) -> &'static str {
+    match self {
+      BalancerStrategy::LeastPing => "leastPing",
+      BalancerStrategy::LeastLoad => "leastLoad",
+      BalancerStrategy::Random => "random",
+      BalancerStrategy::RoundRobin => "roundRobin",
+    }
+  }
+}
+
+/// 负载均衡设置
+///
+/// 启用后当前节点和排名靠前的节点一起作为出站，由 xray 的 burstObservatory 检测，
+/// 不再定时检查当前节点。
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Balancer {
+  /// 是否启用
+  pub enabled: bool,
+  /// 出站的节点数量
+  pub size: u32,
+  /// 策略
+  pub strategy: BalancerStrategy,
+  /// 检测间隔，秒
+  pub interval: u32,
+}
+
+impl Default for Balancer {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      size: 5,
+      strategy: BalancerStrategy::LeastPing,
+      interval: 30,
+    }
+  }
+}
+
+/// 下载测速设置
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedTest {
+  /// 下载用 URL
+  pub url: String,
+  /// 最长下载时间，秒
+  pub duration: u32,
+  /// 最多下载的数据量，MiB
+  pub max_mb: u32,
+  /// 并发量
+  pub concurrency: u32,
+}
+
+impl Default for SpeedTest {
+  fn default() -> Self {
+    Self {
+      url: String::from("https://speed.cloudflare.com/__down?bytes=100000000"),
+      duration: 10,
+      max_mb: 50,
+      concurrency: 2,
+    }
+  }
+}
+
+/// 多路复用设置
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Mux {
+  /// 是否启用
+  pub enabled: bool,
+  /// TCP 最大并发连接数
+  pub concurrency: i16,
+  /// UDP 最大并发连接数
+  pub xudp_concurrency: i16,
+  /// 如何处理 UDP 443：reject、allow 或 skip
+  pub xudp_proxy_udp443: String,
+}
+
+impl Default for Mux {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      concurrency: 8,
+      xudp_concurrency: 16,
+      xudp_proxy_udp443: String::from("reject"),
+    }
+  }
 }
 
 #[derive(Debug, Deserialize, Serialize, Model)]
@@ -30,3 +314,51 @@ pub struct SettingsTable {
   pub id: i64,
   pub settings: Json<Settings>,
 }
+
+/// TLS 分片与 UDP 噪声设置
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Fragment {
+  /// 是否启用，关闭时分片和噪声都不生效
+  pub enabled: bool,
+  /// 分片的数据包，如 tlshello 或 1-3；为空时只发送噪声
+  pub packets: String,
+  /// 分片长度范围，字节
+  pub length: String,
+  /// 分片间隔范围，毫秒
+  pub interval: String,
+  /// UDP 噪声，为空时不发送
+  pub noises: Vec<Noise>,
+}
+
+impl Default for Fragment {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      packets: String::from("tlshello"),
+      length: String::from("100-200"),
+      interval: String::from("10-20"),
+      noises: Vec::new(),
+    }
+  }
+}
+
+impl Fragment {
+  /// 是否需要生成分片出站
+  pub fn is_active(&self) -> bool {
+    self.enabled && (!self.packets.is_empty() || !self.noises.is_empty())
+  }
+}
+
+/// UDP 噪声
+#[derive(Clone, Debug, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct Noise {
+  /// 类型：rand、str 或 base64
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// 内容；rand 类型时为长度范围
+  pub packet: String,
+  /// 发送延迟范围，毫秒
+  pub delay: String,
+}
@@ -1,6 +1,5 @@
 use std::{
   collections::HashSet,
-  str::FromStr,
   sync::{LazyLock, RwLock},
 };
 
@@ -19,6 +18,7 @@ use crate::{
   app_handle::get_app_handle,
   db::{base64::try_base64_decode, notify_change},
   error::{Error, Result},
+  region::{region_from_name, resolve_regions},
 };
 
 use super::{endpoint::Endpoint, DbState};
@@ -38,6 +38,8 @@ pub struct Subscription {
   pub url: String,
   /// 是否禁用
   pub disabled: Option<bool>,
+  /// 本地内容；不为空时不再从 URL 下载
+  pub content: Option<String>,
 }
 
 impl Subscription {
@@ -57,22 +59,23 @@ impl Subscription {
         self.set_updating(&app, false);
       }
 
-      // 下载订阅
-      let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(60))
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0")
-        .build()?;
-      let body = client.get(&self.url).send().await?;
-      let body = body.text().await?;
+      let body = if let Some(content) = &self.content {
+        content.clone()
+      } else {
+        // 下载订阅
+        let client = reqwest::Client::builder()
+          .timeout(std::time::Duration::from_secs(60))
+          .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0")
+          .build()?;
+        let body = client.get(&self.url).send().await?;
+        body.text().await?
+      };
       debug!("String: {}", &body);
 
       // 尝试 base64 解码
       let body = try_base64_decode(body)?;
       debug!("Decoded: {}", &body);
 
-      // 按行分割
-      let lines = body.split("\n");
-
       let state: State<DbState> = app.state();
       let mut db_guard = state.db.lock().await;
       let db = db_guard.as_mut().expect("Database not intialized");
@@ -84,17 +87,12 @@ impl Subscription {
         .await?;
 
       // 插入新的
-      for line in lines {
-        let line = line.trim();
-        debug!("Line: {}", line);
-
-        if line.is_empty() {
-          continue;
-        }
-
-        match Endpoint::from_str(line) {
+      for ep in Endpoint::parse_all(&body) {
+        match ep {
           Ok(ep) => {
             debug!("Endpoint: {:?}", &ep);
+            let region = region_from_name(&ep.name);
+
             if let Err(e) = Endpoint::builder()
               .sub_id(self.id)
               .uri(ep.uri)
@@ -102,6 +100,9 @@ impl Subscription {
               .host(ep.host)
               .port(ep.port)
               .outbound(ep.outbound)
+              .disabled(ep.disabled)
+              .disabled_reason(ep.disabled_reason)
+              .region(region)
               .insert(&mut *db)
               .await
             {
@@ -116,6 +117,11 @@ impl Subscription {
       }
 
       notify_change::<Endpoint>(&app)?;
+      drop(db_guard);
+
+      if let Err(e) = resolve_regions(&app, self.id).await {
+        warn!("Failed to resolve regions of sub {}: {:?}", self.id, e);
+      }
 
       Ok(())
     } else {
@@ -152,3 +158,15 @@ pub fn db_get_updating_subscription_ids() -> Vec<i64> {
   let lock = UPDATING_ONES.read().unwrap();
   Vec::from_iter(lock.to_owned())
 }
+
+/// 订阅的节点统计
+#[derive(Clone, Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionStats {
+  /// 订阅分组 ID
+  pub sub_id: i64,
+  /// 节点总数
+  pub total: u32,
+  /// 不可用的节点数
+  pub disabled: u32,
+}
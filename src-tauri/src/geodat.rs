@@ -0,0 +1,474 @@
+use std::{
+  net::{IpAddr, Ipv4Addr, Ipv6Addr},
+  path::Path,
+};
+
+use regex::Regex;
+
+use crate::error::Result;
+
+/// geosite.dat 中的域名类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainKind {
+  /// 关键字
+  Plain,
+  /// 正则表达式
+  Regex,
+  /// 域名及其子域名
+  Domain,
+  /// 完整匹配
+  Full,
+}
+
+/// geosite.dat 中的域名
+#[derive(Clone, Debug)]
+pub struct Domain {
+  pub kind: DomainKind,
+  pub value: String,
+  /// 属性，如 cn、ads
+  pub attributes: Vec<String>,
+  /// 编译好的正则表达式；无效时为空，不匹配任何主机
+  regex: Option<Regex>,
+}
+
+impl Domain {
+  /// 创建域名，正则表达式类型在这里编译
+  pub fn new(kind: DomainKind, value: String, attributes: Vec<String>) -> Self {
+    let regex = match kind {
+      DomainKind::Regex => Regex::new(&value).ok(),
+      _ => None,
+    };
+
+    Self {
+      kind,
+      value,
+      attributes,
+      regex,
+    }
+  }
+
+  /// 是否匹配主机名
+  pub fn matches(&self, host: &str) -> bool {
+    match self.kind {
+      DomainKind::Plain => host.contains(&self.value),
+      DomainKind::Regex => self.regex.as_ref().is_some_and(|re| re.is_match(host)),
+      DomainKind::Domain => {
+        host == self.value
+          || (host.ends_with(&self.value) && host[..host.len() - self.value.len()].ends_with('.'))
+      }
+      DomainKind::Full => host == self.value,
+    }
+  }
+}
+
+/// geoip.dat 中的网段
+#[derive(Clone, Debug)]
+pub struct Cidr {
+  pub ip: IpAddr,
+  pub prefix: u8,
+}
+
+impl Cidr {
+  /// 解析 1.2.3.0/24 形式的网段，没有前缀长度时为单个地址
+  pub fn parse(s: &str) -> Option<Self> {
+    let (ip, prefix): (IpAddr, Option<u8>) = match s.split_once('/') {
+      Some((ip, prefix)) => (ip.parse().ok()?, Some(prefix.parse().ok()?)),
+      None => (s.parse().ok()?, None),
+    };
+    let prefix = prefix.unwrap_or(if ip.is_ipv4() { 32 } else { 128 });
+
+    Some(Self { ip, prefix })
+  }
+
+  /// 是否包含指定地址
+  pub fn contains(&self, ip: &IpAddr) -> bool {
+    match (self.ip, ip) {
+      (IpAddr::V4(net), IpAddr::V4(ip)) => {
+        let prefix = self.prefix.min(32) as u32;
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        u32::from(net) & mask == u32::from(*ip) & mask
+      }
+      (IpAddr::V6(net), IpAddr::V6(ip)) => {
+        let prefix = self.prefix.min(128) as u32;
+        let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+        u128::from(net) & mask == u128::from(*ip) & mask
+      }
+      _ => false,
+    }
+  }
+}
+
+/// geoip.dat 中的一个分组
+#[derive(Clone, Debug, Default)]
+pub struct GeoIp {
+  pub cidrs: Vec<Cidr>,
+  /// 反向匹配
+  pub reverse_match: bool,
+}
+
+impl GeoIp {
+  /// 是否包含指定地址
+  pub fn contains(&self, ip: &IpAddr) -> bool {
+    self.cidrs.iter().any(|cidr| cidr.contains(ip)) != self.reverse_match
+  }
+}
+
+/// protobuf 字段的值
+enum Field<'a> {
+  Varint(u64),
+  Bytes(&'a [u8]),
+  Fixed,
+}
+
+/// 极简的 protobuf 读取，只够解析 geoip.dat 和 geosite.dat
+struct Reader<'a> {
+  buf: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn new(buf: &'a [u8]) -> Self {
+    Self { buf, pos: 0 }
+  }
+
+  fn varint(&mut self) -> Option<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+      let byte = *self.buf.get(self.pos)?;
+      self.pos += 1;
+      value |= ((byte & 0x7f) as u64) << shift;
+
+      if byte & 0x80 == 0 {
+        return Some(value);
+      }
+    }
+
+    None
+  }
+
+  fn skip(&mut self, len: usize) -> Option<&'a [u8]> {
+    let end = self.pos.checked_add(len)?;
+    let bytes = self.buf.get(self.pos..end)?;
+    self.pos = end;
+    Some(bytes)
+  }
+
+  /// 读取下一个字段，返回字段号和值；读完或数据有误时返回 None
+  fn field(&mut self) -> Option<(u64, Field<'a>)> {
+    if self.pos >= self.buf.len() {
+      return None;
+    }
+
+    let key = self.varint()?;
+    let value = match key & 7 {
+      0 => Field::Varint(self.varint()?),
+      1 => {
+        self.skip(8)?;
+        Field::Fixed
+      }
+      2 => {
+        let len = self.varint()? as usize;
+        Field::Bytes(self.skip(len)?)
+      }
+      5 => {
+        self.skip(4)?;
+        Field::Fixed
+      }
+      _ => return None,
+    };
+
+    Some((key >> 3, value))
+  }
+}
+
+/// 在列表中查找代码为 code 的分组，返回分组的内容
+fn find_entry<'a>(buf: &'a [u8], code: &str) -> Option<&'a [u8]> {
+  let mut list = Reader::new(buf);
+
+  while let Some((num, field)) = list.field() {
+    if let (1, Field::Bytes(entry)) = (num, field) {
+      let mut reader = Reader::new(entry);
+
+      // 代码一般是第一个字段
+      while let Some((num, field)) = reader.field() {
+        if let (1, Field::Bytes(value)) = (num, field) {
+          if String::from_utf8_lossy(value).eq_ignore_ascii_case(code) {
+            return Some(entry);
+          }
+          break;
+        }
+      }
+    }
+  }
+
+  None
+}
+
+fn parse_domain(buf: &[u8]) -> Domain {
+  let mut kind = DomainKind::Plain;
+  let mut value = String::new();
+  let mut attributes = Vec::new();
+  let mut reader = Reader::new(buf);
+
+  while let Some((num, field)) = reader.field() {
+    match (num, field) {
+      (1, Field::Varint(n)) => {
+        kind = match n {
+          1 => DomainKind::Regex,
+          2 => DomainKind::Domain,
+          3 => DomainKind::Full,
+          _ => DomainKind::Plain,
+        }
+      }
+      (2, Field::Bytes(bytes)) => value = String::from_utf8_lossy(bytes).into_owned(),
+      (3, Field::Bytes(attr)) => {
+        let mut reader = Reader::new(attr);
+
+        while let Some((num, field)) = reader.field() {
+          if let (1, Field::Bytes(key)) = (num, field) {
+            attributes.push(String::from_utf8_lossy(key).into_owned());
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  Domain::new(kind, value, attributes)
+}
+
+fn parse_cidr(buf: &[u8]) -> Option<Cidr> {
+  let mut ip = None;
+  let mut prefix = 0;
+  let mut reader = Reader::new(buf);
+
+  while let Some((num, field)) = reader.field() {
+    match (num, field) {
+      (1, Field::Bytes(bytes)) => {
+        ip = match bytes.len() {
+          4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?))),
+          16 => Some(IpAddr::V6(Ipv6Addr::from(
+            <[u8; 16]>::try_from(bytes).ok()?,
+          ))),
+          _ => None,
+        }
+      }
+      (2, Field::Varint(value)) => prefix = value as u8,
+      _ => {}
+    }
+  }
+
+  Some(Cidr { ip: ip?, prefix })
+}
+
+/// 读取 geosite.dat 中的一个分组；分组不存在时返回空列表
+pub fn load_geosite(path: &Path, code: &str) -> Result<Vec<Domain>> {
+  let buf = std::fs::read(path)?;
+  let mut domains = Vec::new();
+
+  if let Some(entry) = find_entry(&buf, code) {
+    let mut reader = Reader::new(entry);
+
+    while let Some((num, field)) = reader.field() {
+      if let (2, Field::Bytes(domain)) = (num, field) {
+        domains.push(parse_domain(domain));
+      }
+    }
+  }
+
+  Ok(domains)
+}
+
+/// 解析 geoip.dat 中的一个分组，返回代码和网段
+fn parse_geoip(buf: &[u8]) -> (String, GeoIp) {
+  let mut code = String::new();
+  let mut geoip = GeoIp::default();
+  let mut reader = Reader::new(buf);
+
+  while let Some((num, field)) = reader.field() {
+    match (num, field) {
+      (1, Field::Bytes(value)) => code = String::from_utf8_lossy(value).into_owned(),
+      (2, Field::Bytes(cidr)) => geoip.cidrs.extend(parse_cidr(cidr)),
+      (3, Field::Varint(value)) => geoip.reverse_match = value != 0,
+      _ => {}
+    }
+  }
+
+  (code, geoip)
+}
+
+/// 读取 geoip.dat 中的一个分组；分组不存在时返回空分组
+pub fn load_geoip(path: &Path, code: &str) -> Result<GeoIp> {
+  let buf = std::fs::read(path)?;
+  Ok(
+    find_entry(&buf, code)
+      .map(|entry| parse_geoip(entry).1)
+      .unwrap_or_default(),
+  )
+}
+
+/// 在 geoip.dat 中查找地址所属的国家或地区，返回大写的两位代码
+///
+/// 只看两位代码的分组，跳过 private、telegram 等非地区分组。
+pub fn lookup_geoip(path: &Path, ips: &[IpAddr]) -> Result<Vec<Option<String>>> {
+  let buf = std::fs::read(path)?;
+  let mut regions = vec![None; ips.len()];
+  let mut list = Reader::new(&buf);
+
+  while let Some((num, field)) = list.field() {
+    if let (1, Field::Bytes(entry)) = (num, field) {
+      let (code, geoip) = parse_geoip(entry);
+
+      if code.len() != 2 {
+        continue;
+      }
+
+      for (ip, region) in ips.iter().zip(regions.iter_mut()) {
+        if region.is_none() && geoip.contains(ip) {
+          *region = Some(code.to_uppercase());
+        }
+      }
+    }
+  }
+
+  Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 编码一个长度前缀的字段
+  fn bytes_field(num: u8, value: &[u8]) -> Vec<u8> {
+    let mut buf = vec![(num << 3) | 2, value.len() as u8];
+    buf.extend_from_slice(value);
+    buf
+  }
+
+  /// 由类型和值构造 geosite.dat 中的域名
+  fn domain(kind: u8, value: &str) -> Domain {
+    parse_domain(&[&[0x08, kind][..], &bytes_field(2, value.as_bytes())].concat())
+  }
+
+  #[test]
+  fn reader_reads_varints_and_fields() {
+    let mut reader = Reader::new(&[0xac, 0x02]);
+    assert_eq!(reader.varint(), Some(300));
+    assert_eq!(reader.varint(), None);
+
+    let buf = [&[0x08, 0x96, 0x01][..], &bytes_field(2, b"cn")[..]].concat();
+    let mut reader = Reader::new(&buf);
+    assert!(matches!(reader.field(), Some((1, Field::Varint(150)))));
+    assert!(matches!(reader.field(), Some((2, Field::Bytes(b"cn")))));
+    assert!(reader.field().is_none());
+  }
+
+  #[test]
+  fn reader_stops_on_truncated_data() {
+    let mut reader = Reader::new(&[0x12, 0x05, b'a']);
+    assert!(reader.field().is_none());
+  }
+
+  #[test]
+  fn cidr_contains() {
+    let net = Cidr::parse("10.1.0.0/16").unwrap();
+    assert!(net.contains(&"10.1.2.3".parse().unwrap()));
+    assert!(!net.contains(&"10.2.0.1".parse().unwrap()));
+    assert!(!net.contains(&"::1".parse().unwrap()));
+
+    let all = Cidr::parse("0.0.0.0/0").unwrap();
+    assert!(all.contains(&"8.8.8.8".parse().unwrap()));
+
+    let single = Cidr::parse("2001:db8::1").unwrap();
+    assert_eq!(single.prefix, 128);
+    assert!(single.contains(&"2001:db8::1".parse().unwrap()));
+    assert!(!single.contains(&"2001:db8::2".parse().unwrap()));
+  }
+
+  #[test]
+  fn geoip_reverse_match() {
+    let geoip = GeoIp {
+      cidrs: vec![parse_cidr(&[bytes_field(1, &[10, 0, 0, 0]), vec![0x10, 8]].concat()).unwrap()],
+      reverse_match: true,
+    };
+    assert!(!geoip.contains(&"10.1.2.3".parse().unwrap()));
+    assert!(geoip.contains(&"1.1.1.1".parse().unwrap()));
+  }
+
+  #[test]
+  fn finds_geosite_entry_by_code() {
+    let attr = bytes_field(1, b"ads");
+    let domain = [
+      &[0x08, 2][..],
+      &bytes_field(2, b"example.com"),
+      &bytes_field(3, &attr),
+    ]
+    .concat();
+    let entry = [bytes_field(1, b"CN"), bytes_field(2, &domain)].concat();
+    let list = bytes_field(1, &entry);
+
+    let mut reader = Reader::new(find_entry(&list, "cn").unwrap());
+    reader.field();
+    let Some((2, Field::Bytes(buf))) = reader.field() else {
+      panic!("missing domain");
+    };
+    let domain = parse_domain(buf);
+    assert_eq!(domain.kind, DomainKind::Domain);
+    assert_eq!(domain.value, "example.com");
+    assert_eq!(domain.attributes, ["ads"]);
+    assert!(find_entry(&list, "us").is_none());
+  }
+
+  #[test]
+  fn domain_matches() {
+    let suffix = domain(2, "example.com");
+    assert!(suffix.matches("example.com"));
+    assert!(suffix.matches("www.example.com"));
+    assert!(!suffix.matches("badexample.com"));
+
+    assert!(domain(0, "google").matches("www.google.com"));
+    assert!(domain(3, "a.com").matches("a.com"));
+    assert!(!domain(3, "a.com").matches("www.a.com"));
+
+    let regex = domain(1, r"^ads\d+\.");
+    assert!(regex.matches("ads1.example.com"));
+    assert!(!regex.matches("www.example.com"));
+
+    // 正则表达式在创建时编译，无效的不匹配任何主机
+    assert!(regex.regex.is_some());
+    let invalid = domain(1, "(");
+    assert!(invalid.regex.is_none());
+    assert!(!invalid.matches("("));
+  }
+
+  #[test]
+  fn finds_geoip_entry_by_code() {
+    let cidr = [bytes_field(1, &[1, 2, 3, 0]), vec![0x10, 24]].concat();
+    let entry = [bytes_field(1, b"JP"), bytes_field(2, &cidr)].concat();
+    let list = bytes_field(1, &entry);
+
+    let (code, geoip) = parse_geoip(find_entry(&list, "jp").unwrap());
+    assert_eq!(code, "JP");
+    assert!(geoip.contains(&"1.2.3.4".parse().unwrap()));
+    assert!(!geoip.contains(&"1.2.4.4".parse().unwrap()));
+    assert!(find_entry(&list, "us").is_none());
+  }
+
+  #[test]
+  fn lookup_geoip_only_uses_region_codes() {
+    let all = [bytes_field(1, &[0, 0, 0, 0]), vec![0x10, 0]].concat();
+    let private = [bytes_field(1, b"private"), bytes_field(2, &all)].concat();
+    let cidr = [bytes_field(1, &[1, 2, 3, 0]), vec![0x10, 24]].concat();
+    let jp = [bytes_field(1, b"jp"), bytes_field(2, &cidr)].concat();
+    let list = [bytes_field(1, &private), bytes_field(1, &jp)].concat();
+
+    let path = std::env::temp_dir().join(format!("geoip-test-{}.dat", std::process::id()));
+    std::fs::write(&path, list).unwrap();
+    let ips = ["1.2.3.4".parse().unwrap(), "8.8.8.8".parse().unwrap()];
+    let regions = lookup_geoip(&path, &ips);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(regions.unwrap(), [Some(String::from("JP")), None]);
+  }
+}
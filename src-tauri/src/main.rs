@@ -5,27 +5,45 @@ mod app_handle;
 mod command;
 mod db;
 mod error;
+mod geodat;
+mod ping;
+mod region;
 mod xray;
 
 use std::fs;
 
 use app_handle::set_app_handle;
 use command::{
+  diagnose::diagnose_website,
   endpoint::{
-    get_current_endpoint, select_fastest_endpoint, set_current_endpoint,
-    start_check_current_endpoint, XrayState,
+    get_balancer_status, get_current_endpoint, get_current_group, get_endpoint_locked,
+    select_fastest_endpoint, select_fastest_endpoint_for_website, set_current_endpoint,
+    set_current_group, set_endpoint_locked, set_fragment_enabled, start_check_current_endpoint,
+    test_endpoint_latencies, test_endpoint_latency, test_speeds, test_subscription_latencies,
+    test_website_matrix, XrayState,
   },
-  subscription::{update_subscription, update_subscriptions},
+  endpoint_settings::{
+    clear_endpoint_patch, get_endpoint_flags, get_endpoint_fragment, get_endpoint_mux,
+    get_endpoint_patch, get_endpoint_upstream, set_endpoint_blacklisted, set_endpoint_favorite,
+    set_endpoint_fragment, set_endpoint_mux, set_endpoint_patch, set_endpoint_upstream,
+  },
+  network::get_network_status,
+  subscription::{import_outbounds, update_subscription, update_subscriptions},
+  test_run::{cancel_test_run, get_test_run_ids},
   update_geosites,
 };
 use db::{
-  db_count_endpoints, db_count_subscriptions, db_get_settings, db_insert_subscription,
-  db_insert_website, db_query_endpoints, db_query_flows, db_query_logs, db_query_subscriptions,
-  db_query_websites, db_remove_subscription, db_remove_website, db_set_settings,
+  db_count_endpoints, db_count_endpoints_by_subscription, db_count_subscriptions,
+  db_get_endpoint_details, db_get_latency_stats, db_get_settings, db_insert_endpoint_group,
+  db_insert_subscription, db_insert_website, db_query_endpoint_groups, db_query_endpoints,
+  db_query_flows, db_query_latency_logs, db_query_logs, db_query_subscriptions,
+  db_query_website_latencies, db_query_websites, db_remove_endpoint_group, db_remove_subscription,
+  db_remove_website, db_search_endpoint_details, db_set_settings, db_update_endpoint_group,
   db_update_subscription, initialize, subscription::db_get_updating_subscription_ids, DbState,
 };
 use error::{map_anything, Result};
-use log::LevelFilter;
+use log::{error, warn, LevelFilter};
+use region::resolve_missing_regions;
 use tauri::{
   App, AppHandle, CustomMenuItem, Manager, State, SystemTray, SystemTrayEvent, SystemTrayMenu,
   SystemTrayMenuItem, WindowBuilder,
@@ -73,7 +91,7 @@ fn copy_resource_if_not_exists(app: &App, filename: &str) -> Result<()> {
 /// 导出 API 绑定代码
 #[cfg(debug_assertions)]
 fn export_bindings() {
-  use command::query_stats::AllStats;
+  use command::{query_stats::AllStats, test_run::TestProgress};
   //use db::settings::Settings;
   use specta::{
     collect_types,
@@ -83,10 +101,12 @@ fn export_bindings() {
   let config = ExportConfiguration::new().bigint(BigIntExportBehavior::Number);
 
   println!("{}", specta::ts::export::<AllStats>(&config).unwrap());
+  println!("{}", specta::ts::export::<TestProgress>(&config).unwrap());
 
   tauri_specta::ts::export_with_cfg(
     collect_types![
       db_count_endpoints,
+      db_count_endpoints_by_subscription,
       db_count_subscriptions,
       db_get_settings,
       db_insert_subscription,
@@ -106,6 +126,44 @@ fn export_bindings() {
       update_subscription,
       update_subscriptions,
       db_get_updating_subscription_ids,
+      import_outbounds,
+      get_endpoint_upstream,
+      set_endpoint_upstream,
+      get_endpoint_mux,
+      set_endpoint_mux,
+      get_endpoint_fragment,
+      set_endpoint_fragment,
+      set_fragment_enabled,
+      db_get_endpoint_details,
+      db_search_endpoint_details,
+      get_endpoint_patch,
+      set_endpoint_patch,
+      clear_endpoint_patch,
+      db_query_latency_logs,
+      db_get_latency_stats,
+      test_speeds,
+      test_endpoint_latency,
+      test_endpoint_latencies,
+      test_subscription_latencies,
+      cancel_test_run,
+      get_test_run_ids,
+      db_query_website_latencies,
+      test_website_matrix,
+      select_fastest_endpoint_for_website,
+      diagnose_website,
+      get_network_status,
+      get_endpoint_flags,
+      set_endpoint_favorite,
+      set_endpoint_blacklisted,
+      get_endpoint_locked,
+      set_endpoint_locked,
+      get_balancer_status,
+      set_current_group,
+      get_current_group,
+      db_query_endpoint_groups,
+      db_insert_endpoint_group,
+      db_update_endpoint_group,
+      db_remove_endpoint_group,
     ]
     .unwrap(),
     config,
@@ -229,17 +287,31 @@ fn main() {
         *db_guard = Some(db);
       });
 
+      // 补全升级前导入的节点的地区
+      let handle = app.handle();
+      tauri::async_runtime::spawn(async move {
+        if let Err(e) = resolve_missing_regions(&handle).await {
+          warn!("Failed to resolve missing regions: {:?}", e);
+        }
+      });
+
       // 更新订阅并开启计时器
       let handle = app.handle();
       tauri::async_runtime::spawn(async move {
-        update_subscriptions(handle).await.unwrap();
-        start_check_current_endpoint().await.unwrap();
+        if let Err(e) = update_subscriptions(handle).await {
+          error!("Failed to update subscriptions: {:?}", e);
+        }
+
+        if let Err(e) = start_check_current_endpoint().await {
+          error!("Failed to start checking current endpoint: {:?}", e);
+        }
       });
 
       Ok(())
     })
     .invoke_handler(tauri::generate_handler![
       db_count_endpoints,
+      db_count_endpoints_by_subscription,
       db_count_subscriptions,
       db_get_settings,
       db_insert_subscription,
@@ -259,6 +331,44 @@ fn main() {
       update_subscription,
       update_subscriptions,
       db_get_updating_subscription_ids,
+      import_outbounds,
+      get_endpoint_upstream,
+      set_endpoint_upstream,
+      get_endpoint_mux,
+      set_endpoint_mux,
+      get_endpoint_fragment,
+      set_endpoint_fragment,
+      set_fragment_enabled,
+      db_get_endpoint_details,
+      db_search_endpoint_details,
+      get_endpoint_patch,
+      set_endpoint_patch,
+      clear_endpoint_patch,
+      db_query_latency_logs,
+      db_get_latency_stats,
+      test_speeds,
+      test_endpoint_latency,
+      test_endpoint_latencies,
+      test_subscription_latencies,
+      cancel_test_run,
+      get_test_run_ids,
+      db_query_website_latencies,
+      test_website_matrix,
+      select_fastest_endpoint_for_website,
+      diagnose_website,
+      get_network_status,
+      get_endpoint_flags,
+      set_endpoint_favorite,
+      set_endpoint_blacklisted,
+      get_endpoint_locked,
+      set_endpoint_locked,
+      get_balancer_status,
+      set_current_group,
+      get_current_group,
+      db_query_endpoint_groups,
+      db_insert_endpoint_group,
+      db_update_endpoint_group,
+      db_remove_endpoint_group,
     ])
     .build(tauri::generate_context!())
     .expect("error while running tauri application")
@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+use tokio::{net::TcpStream, time::timeout};
+use tokio_native_tls::{native_tls, TlsConnector};
+
+use crate::db::{
+  endpoint::Endpoint,
+  latency::{TestOutcome, TestResult},
+  settings::PingMode,
+};
+
+/// 预检超时
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 不经过 xray，直接连接节点
+pub async fn ping_endpoint(ep: &Endpoint, sni: Option<&str>, mode: PingMode) -> TestResult {
+  let now = Instant::now();
+  let result = timeout(PING_TIMEOUT, async {
+    let stream = TcpStream::connect((ep.host.as_str(), ep.port))
+      .await
+      .map_err(|e| TestOutcome::from_io_error(&e))?;
+
+    if mode == PingMode::Tls {
+      if let Some(sni) = sni {
+        // 只关心握手能否完成，不校验证书
+        let connector = native_tls::TlsConnector::builder()
+          .danger_accept_invalid_certs(true)
+          .danger_accept_invalid_hostnames(true)
+          .build()
+          .map_err(|_| TestOutcome::Other)?;
+        TlsConnector::from(connector)
+          .connect(sni, stream)
+          .await
+          .map_err(|_| TestOutcome::Tls)?;
+      }
+    }
+
+    Ok(())
+  })
+  .await;
+
+  match result {
+    Ok(Ok(())) => Ok(now.elapsed().as_millis() as i32),
+    Ok(Err(outcome)) => Err(outcome),
+    Err(_) => Err(TestOutcome::Timeout),
+  }
+}
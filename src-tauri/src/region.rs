@@ -0,0 +1,280 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use log::{debug, info};
+use ormlite::{Model, Row};
+use tauri::{AppHandle, Manager, State};
+use tokio::{net::lookup_host, sync::Semaphore, task::JoinSet, time::timeout};
+
+use crate::{
+  db::{endpoint::Endpoint, get_settings, notify_change, DbState},
+  error::{map_anything, Result},
+  geodat::lookup_geoip,
+};
+
+/// 名称中常见的地区关键字，英文按单词匹配，不区分大小写
+const KEYWORDS: &[(&str, &[&str])] = &[
+  ("HK", &["香港", "Hong Kong", "HongKong", "HK"]),
+  ("TW", &["台湾", "台灣", "台北", "Taiwan", "Taipei", "TW"]),
+  ("MO", &["澳门", "澳門", "Macau", "Macao"]),
+  (
+    "JP",
+    &[
+      "日本", "东京", "東京", "大阪", "Japan", "Tokyo", "Osaka", "JP",
+    ],
+  ),
+  (
+    "KR",
+    &["韩国", "韓國", "首尔", "首爾", "Korea", "Seoul", "KR"],
+  ),
+  ("SG", &["新加坡", "狮城", "Singapore", "SG"]),
+  (
+    "US",
+    &[
+      "美国",
+      "美國",
+      "洛杉矶",
+      "硅谷",
+      "西雅图",
+      "纽约",
+      "芝加哥",
+      "United States",
+      "America",
+      "Los Angeles",
+      "San Jose",
+      "Seattle",
+      "New York",
+      "Chicago",
+      "US",
+      "USA",
+    ],
+  ),
+  ("CA", &["加拿大", "Canada"]),
+  (
+    "GB",
+    &[
+      "英国",
+      "英國",
+      "伦敦",
+      "United Kingdom",
+      "Britain",
+      "London",
+      "UK",
+      "GB",
+    ],
+  ),
+  (
+    "DE",
+    &["德国", "德國", "法兰克福", "Germany", "Frankfurt", "DE"],
+  ),
+  ("FR", &["法国", "法國", "巴黎", "France", "Paris", "FR"]),
+  (
+    "NL",
+    &[
+      "荷兰",
+      "荷蘭",
+      "阿姆斯特丹",
+      "Netherlands",
+      "Amsterdam",
+      "NL",
+    ],
+  ),
+  (
+    "RU",
+    &["俄罗斯", "俄羅斯", "莫斯科", "Russia", "Moscow", "RU"],
+  ),
+  (
+    "AU",
+    &["澳大利亚", "澳洲", "悉尼", "Australia", "Sydney", "AU"],
+  ),
+  ("IN", &["印度", "孟买", "India", "Mumbai"]),
+  ("TR", &["土耳其", "Turkey", "Türkiye"]),
+  ("MY", &["马来西亚", "馬來西亞", "Malaysia"]),
+  ("TH", &["泰国", "泰國", "Thailand"]),
+  ("VN", &["越南", "Vietnam"]),
+  ("PH", &["菲律宾", "菲律賓", "Philippines"]),
+  ("ID", &["印尼", "印度尼西亚", "Indonesia"]),
+  ("AR", &["阿根廷", "Argentina"]),
+  ("BR", &["巴西", "Brazil"]),
+];
+
+/// 解析域名的超时
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 从节点名称中识别地区，返回大写的两位代码
+///
+/// 优先使用旗帜 emoji，其次是地区关键字。
+pub fn region_from_name(name: &str) -> Option<String> {
+  region_from_flag(name).or_else(|| region_from_keywords(name))
+}
+
+/// 旗帜 emoji 由两个区域指示符组成，对应两位地区代码
+fn region_from_flag(name: &str) -> Option<String> {
+  let indicator = |c: char| {
+    let offset = (c as u32).checked_sub(0x1F1E6)?;
+    (offset < 26).then(|| (b'A' + offset as u8) as char)
+  };
+  let chars: Vec<char> = name.chars().collect();
+
+  chars
+    .windows(2)
+    .find_map(|pair| Some([indicator(pair[0])?, indicator(pair[1])?].iter().collect()))
+}
+
+fn region_from_keywords(name: &str) -> Option<String> {
+  // 英文单词前后补空格，数字也作为分隔，如 HK01
+  let words: String = name
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphabetic() {
+        c.to_ascii_lowercase()
+      } else {
+        ' '
+      }
+    })
+    .collect();
+  let words = format!(" {} ", words);
+
+  KEYWORDS.iter().find_map(|(code, keywords)| {
+    let matched = keywords.iter().any(|keyword| {
+      if keyword.is_ascii() {
+        words.contains(&format!(" {} ", keyword.to_ascii_lowercase()))
+      } else {
+        name.contains(keyword)
+      }
+    });
+    matched.then(|| String::from(*code))
+  })
+}
+
+/// 名称中没有地区的节点，解析地址后在 geoip.dat 中查找
+pub async fn resolve_regions(app: &AppHandle, sub_id: i64) -> Result<()> {
+  let eps = {
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+
+    Endpoint::select()
+      .where_bind("sub_id = ?", sub_id)
+      .where_("region IS NULL")
+      .fetch_all(db)
+      .await?
+  };
+
+  if eps.is_empty() {
+    return Ok(());
+  }
+
+  // 与测速相同的并发量，避免同时发起大量 DNS 查询
+  let settings = get_settings(app).await?;
+  let sem = Arc::new(Semaphore::new(settings.ep_test_concurrency as usize));
+  let mut set = JoinSet::new();
+
+  for ep in eps {
+    let permit = Arc::clone(&sem).acquire_owned().await;
+
+    set.spawn(async move {
+      let _permit = permit;
+      let ip = match ep.host.parse::<IpAddr>() {
+        Ok(ip) => Some(ip),
+        Err(_) => timeout(RESOLVE_TIMEOUT, lookup_host((ep.host.as_str(), ep.port)))
+          .await
+          .ok()
+          .and_then(|addrs| addrs.ok()?.next())
+          .map(|addr| addr.ip()),
+      };
+      (ep.id, ip)
+    });
+  }
+
+  let mut resolved = Vec::new();
+
+  while let Some(result) = set.join_next().await {
+    if let Ok((id, Some(ip))) = result {
+      resolved.push((id, ip));
+    }
+  }
+
+  let path = app
+    .path_resolver()
+    .app_data_dir()
+    .ok_or_else(|| map_anything("No app data dir"))?
+    .join("geoip.dat");
+  let ips: Vec<_> = resolved.iter().map(|(_, ip)| *ip).collect();
+  let regions = tauri::async_runtime::spawn_blocking(move || lookup_geoip(&path, &ips)).await??;
+
+  let state: State<DbState> = app.state();
+  let mut db_guard = state.db.lock().await;
+  let db = db_guard.as_mut().expect("Database not intialized");
+  let mut count = 0;
+
+  for ((id, ip), region) in resolved.into_iter().zip(regions) {
+    if let Some(region) = region {
+      debug!("Endpoint {} at {} is in {}", id, ip, &region);
+      ormlite::query("UPDATE endpoint SET region = ? WHERE id = ?")
+        .bind(region)
+        .bind(id)
+        .fetch_optional(&mut *db)
+        .await?;
+      count += 1;
+    }
+  }
+
+  info!("Resolved regions of {} endpoints in sub {}", count, sub_id);
+  notify_change::<Endpoint>(app)
+}
+
+/// 查找所有还没有地区的节点，如升级前导入、之后订阅没有更新成功的
+pub async fn resolve_missing_regions(app: &AppHandle) -> Result<()> {
+  let rows = {
+    let state: State<DbState> = app.state();
+    let mut db_guard = state.db.lock().await;
+    let db = db_guard.as_mut().expect("Database not intialized");
+
+    ormlite::query("SELECT DISTINCT sub_id FROM endpoint WHERE region IS NULL")
+      .fetch_all(db)
+      .await?
+  };
+
+  for row in rows {
+    resolve_regions(app, row.try_get::<i64, usize>(0)?).await?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn region_from_flag_emoji() {
+    assert_eq!(region_from_flag("🇯🇵 Tokyo 01"), Some(String::from("JP")));
+    assert_eq!(region_from_flag("节点 🇺🇸"), Some(String::from("US")));
+    assert_eq!(region_from_flag("Tokyo 01"), None);
+  }
+
+  #[test]
+  fn region_from_name_keywords() {
+    assert_eq!(region_from_keywords("香港 01"), Some(String::from("HK")));
+    assert_eq!(region_from_keywords("HK01 IPLC"), Some(String::from("HK")));
+    assert_eq!(
+      region_from_keywords("los angeles"),
+      Some(String::from("US"))
+    );
+    // 英文按单词匹配，Auto 中的 AU 不算
+    assert_eq!(region_from_keywords("Auto Select"), None);
+    assert_eq!(region_from_keywords("Unknown"), None);
+  }
+
+  #[test]
+  fn flag_takes_precedence_over_keywords() {
+    assert_eq!(
+      region_from_name("🇸🇬 Hong Kong relay"),
+      Some(String::from("SG"))
+    );
+    assert_eq!(
+      region_from_name("Hong Kong relay"),
+      Some(String::from("HK"))
+    );
+  }
+}
@@ -1,7 +1,10 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+  collections::{HashMap, HashSet},
+  path::PathBuf,
+};
 
 use anyhow::anyhow;
-use log::{info, warn};
+use log::{debug, info, warn};
 use serde_json::{json, Value};
 use tauri::{
   api::process::{Command, CommandChild, CommandEvent, Encoding},
@@ -12,14 +15,19 @@ use tokio::sync::mpsc::Receiver;
 use crate::{
   app_handle::get_app_handle,
   command::get_available_port,
-  db::{endpoint::Endpoint, get_settings, insert_log},
+  db::{
+    endpoint::{json_merge, Endpoint},
+    endpoint_settings::{get_endpoint_settings, resolve_upstreams},
+    get_settings, insert_log,
+    settings::{AllowInsecure, Balancer, Fragment, Mux, Settings},
+  },
   error::{Error, Result},
 };
 
 /// Xray 命令
 pub struct Xray {
-  /// 节点
-  ep: Endpoint,
+  /// 节点；批量测试时有多个
+  eps: Vec<Endpoint>,
   /// 配置文件全路径
   filename: Option<String>,
   /// xray 命令
@@ -27,23 +35,61 @@ pub struct Xray {
   rx: Option<Receiver<CommandEvent>>,
   /// 监听端口
   port: Option<u16>,
+  /// 批量测试时各节点的 socks 端口；无法生成出站的节点为空
+  test_ports: Vec<Option<u16>>,
+  /// 负载均衡设置；为空时多个节点表示批量测试
+  balancer: Option<Balancer>,
 }
 
 impl Xray {
   /// 创建新的 Xray 命令
   pub fn new(endpoint: Endpoint) -> Self {
+    Self::new_batch(vec![endpoint])
+  }
+
+  /// 创建同时测试多个节点的 Xray 命令
+  pub fn new_batch(endpoints: Vec<Endpoint>) -> Self {
     Xray {
-      ep: endpoint,
+      eps: endpoints,
       filename: None,
       child: None,
       rx: None,
       port: None,
+      test_ports: Vec::new(),
+      balancer: None,
     }
   }
 
-  /// 节点
+  /// 创建负载均衡的 Xray 命令，第一个节点为首选
+  pub fn new_balancer(endpoints: Vec<Endpoint>, balancer: Balancer) -> Self {
+    let mut xray = Self::new_batch(endpoints);
+    xray.balancer = Some(balancer);
+    xray
+  }
+
+  /// 节点；负载均衡时为首选的节点
   pub fn endpoint(&self) -> &Endpoint {
-    &self.ep
+    &self.eps[0]
+  }
+
+  /// 全部节点
+  pub fn endpoints(&self) -> &[Endpoint] {
+    &self.eps
+  }
+
+  /// 更新节点记录，如更新订阅后的新 ID；出站不变，不需要重启
+  pub fn rebind(&mut self, endpoints: Vec<Endpoint>) {
+    self.eps = endpoints;
+  }
+
+  /// 负载均衡的全部节点，出站 tag 为 proxy-序号
+  pub fn balanced_endpoints(&self) -> Option<&[Endpoint]> {
+    self.balancer.as_ref().map(|_| self.eps.as_slice())
+  }
+
+  /// 是否为批量测试
+  fn is_batch(&self) -> bool {
+    self.eps.len() > 1 && self.balancer.is_none()
   }
 
   /// 监听端口
@@ -51,10 +97,24 @@ impl Xray {
     self.port
   }
 
+  /// 批量测试时各节点的 socks 端口，与节点一一对应
+  pub fn test_ports(&self) -> &[Option<u16>] {
+    &self.test_ports
+  }
+
+  /// 用于日志的名称
+  fn display_name(&self) -> String {
+    if self.eps.len() > 1 {
+      format!("{} endpoints", self.eps.len())
+    } else {
+      self.endpoint().name.clone()
+    }
+  }
+
   /// 启动 xray
   pub async fn start(&mut self, rule: &str) -> Result<()> {
     if self.child.is_some() {
-      warn!("Xray for {} is already started", self.ep.name);
+      warn!("Xray for {} is already started", self.display_name());
       return Ok(());
     }
 
@@ -63,10 +123,14 @@ impl Xray {
       let data_dir = resolver.app_data_dir().unwrap();
       let mut fullpath = resolver.app_config_dir().unwrap();
       fullpath.push("config");
-      fullpath.push(format!("{}.json", self.ep.id));
+      fullpath.push(if self.is_batch() {
+        format!("batch-{}.json", self.endpoint().id)
+      } else {
+        format!("{}.json", self.endpoint().id)
+      });
       let filename = fullpath.clone().into_os_string().into_string().unwrap();
 
-      self.save_config_file(rule, &fullpath).await?;
+      self.save_config_file(&app, rule, &fullpath).await?;
 
       // 启动 xray
       let cmd = Command::new_sidecar("xray")?
@@ -78,7 +142,7 @@ impl Xray {
         .encoding(Encoding::for_label(b"utf-8").unwrap());
 
       let (rx, child) = cmd.spawn()?;
-      let log = format!("[{}] Xray started for {}", child.pid(), self.ep.name);
+      let log = format!("[{}] Xray started for {}", child.pid(), self.display_name());
       info!("{}", &log);
       insert_log(&app, log).await?;
 
@@ -105,6 +169,7 @@ impl Xray {
 
     self.port = None;
     self.filename = None;
+    self.test_ports.clear();
 
     Ok(())
   }
@@ -141,14 +206,14 @@ impl Xray {
   }
 
   /// 将配置保存为文件
-  async fn save_config_file(&mut self, rule: &str, filename: &PathBuf) -> Result<()> {
-    // 入站配置
-    let (inbounds, port) = get_inbound_objects(rule == "test").await?;
-    self.port = Some(port);
-
+  async fn save_config_file(
+    &mut self,
+    app: &AppHandle,
+    rule: &str,
+    filename: &PathBuf,
+  ) -> Result<()> {
     // 出站配置
-    let outbound: Value = serde_json::from_str(self.ep.outbound.as_str())?;
-    let outbounds = vec![
+    let mut outbounds = vec![
       json!({
         "tag": "direct",
         "protocol": "freedom",
@@ -162,85 +227,45 @@ impl Xray {
           }
         }
       }),
-      outbound,
     ];
 
-    // 路由规则
-    let mut rules = Vec::new();
-
-    match rule {
-      // 默认路由规则
-      "default" => {
-        rules.push(json!({
-          "type": "field",
-          "outboundTag": "block",
-          "domain": [
-            "activity.meteor.com",
-            "geosite:category-ads-all",
-          ]
-        }));
-        rules.push(json!({
-          "type": "field",
-          "outboundTag": "direct",
-          "domain": [
-            "domain:cypress.io",
-            "geosite:cn",
-            "geosite:private",
-            "geosite:apple-cn",
-            "geosite:google-cn",
-            "geosite:tld-cn",
-            "geosite:category-games@cn",
-          ],
-        }));
-        rules.push(json!({
-          "type": "field",
-          "outboundTag": "direct",
-          "ip": [
-            "8.8.8.8/32",
-            "223.5.5.5/32",
-            "119.29.29.29/32",
-            "180.76.76.76/32",
-            "114.114.114.114/32",
-            "geoip:private",
-            "geoip:cn",
-          ],
-        }));
-      }
+    // 入站配置；批量测试时每个节点一个 socks 入站，路由到各自的出站
+    let (inbounds, mut rules, port) = if self.is_batch() {
+      let (inbounds, rules) = self.batch_objects(app, &mut outbounds).await?;
+      (inbounds, rules, self.port.unwrap_or_default())
+    } else if self.balancer.is_some() {
+      let (inbounds, port) = get_inbound_objects(false).await?;
+      self.port = Some(port);
+      self.balanced_outbounds(app, &mut outbounds).await?;
+      (inbounds, Vec::new(), port)
+    } else {
+      let (inbounds, port) = get_inbound_objects(rule == "test").await?;
+      self.port = Some(port);
 
-      // 代理全部数据
-      "all" => {
-        rules.push(json!({
-          "type": "field",
-          "outboundTag": "direct",
-          "domain": [
-            "domain:cypress.io",
-            "geosite:private",
-          ],
-        }));
-        rules.push(json!({
-          "type": "field",
-          "outboundTag": "direct",
-          "ip": [
-            "8.8.8.8/32",
-            "223.5.5.5/32",
-            "119.29.29.29/32",
-            "180.76.76.76/32",
-            "114.114.114.114/32",
-            "geoip:private",
-          ],
-        }));
+      if rule == "test" {
+        self.test_ports = vec![Some(port)];
       }
+      outbounds.extend(endpoint_outbounds(app, self.endpoint(), "proxy").await?);
+      (inbounds, Vec::new(), port)
+    };
 
-      // 测试不加任何规则
-      _ => {}
+    // 路由规则
+    rules.extend(routing_rules(rule));
+
+    if self.balancer.is_some() {
+      rules.push(json!({
+        "type": "field",
+        "port": "0-65535",
+        "balancerTag": "proxy",
+      }));
+    } else if self.eps.len() == 1 {
+      rules.push(json!({
+        "type": "field",
+        "port": "0-65535",
+        "outboundTag": "proxy",
+      }));
     }
 
-    rules.push(json!({
-      "type": "field",
-      "port": "0-65535",
-      "outboundTag": "proxy",
-    }));
-
     let api = if rule == "test" {
       json!({
         "tag": "api",
@@ -253,12 +278,14 @@ impl Xray {
         "tag": "api",
         "listen": format!("127.0.0.1:{}", port),
         "services": [
-          "StatsService"
+          "StatsService",
+          "RoutingService",
+          "ObservatoryService"
         ]
       })
     };
 
-    let config = json!({
+    let mut config = json!({
       "api": api,
       "dns": {
         "hosts": {
@@ -316,19 +343,379 @@ impl Xray {
       "outbounds": outbounds,
     });
 
+    if let Some(balancer) = &self.balancer {
+      let settings = get_settings(app).await?;
+      config["routing"]["balancers"] = json!([{
+        "tag": "proxy",
+        "selector": ["proxy-"],
+        "strategy": {
+          "type": balancer.strategy.as_xray(),
+        },
+        "fallbackTag": "proxy-0",
+      }]);
+      config["burstObservatory"] = json!({
+        "subjectSelector": ["proxy-"],
+        "pingConfig": {
+          "destination": settings.ep_test_url,
+          "interval": format!("{}s", balancer.interval.max(5)),
+          "sampling": 3,
+          "timeout": format!("{}s", settings.probe.timeout.max(1)),
+        },
+      });
+    }
+
     // 保存文件
     tokio::fs::write(filename, config.to_string()).await?;
     Ok(())
   }
+
+  /// 生成负载均衡的出站，tag 为 proxy-序号；个别节点无法生成出站时跳过
+  async fn balanced_outbounds(&self, app: &AppHandle, outbounds: &mut Vec<Value>) -> Result<()> {
+    for (i, ep) in self.eps.iter().enumerate() {
+      match endpoint_outbounds(app, ep, &format!("proxy-{}", i)).await {
+        Ok(ep_outbounds) => outbounds.extend(ep_outbounds),
+        Err(e) if i == 0 => return Err(e),
+        Err(e) => warn!("Skip endpoint {} in balancer: {:?}", ep.id, e),
+      }
+    }
+
+    Ok(())
+  }
+
+  /// 生成批量测试用的入站和路由规则，出站加入 outbounds
+  async fn batch_objects(
+    &mut self,
+    app: &AppHandle,
+    outbounds: &mut Vec<Value>,
+  ) -> Result<(Vec<Value>, Vec<Value>)> {
+    let mut inbounds = Vec::new();
+    let mut rules = Vec::new();
+    let mut used_ports = HashSet::new();
+    self.test_ports.clear();
+
+    for (i, ep) in self.eps.iter().enumerate() {
+      let tag = batch_tag(i);
+
+      // 个别节点无法生成出站时跳过，不影响其他节点
+      let ep_outbounds = match endpoint_outbounds(app, ep, &tag).await {
+        Ok(ep_outbounds) => ep_outbounds,
+        Err(e) => {
+          warn!("Skip endpoint {} in batch: {:?}", ep.id, e);
+          self.test_ports.push(None);
+          continue;
+        }
+      };
+
+      // 端口释放后可能被再次分配，需要去重
+      let port = loop {
+        let port = get_available_port()?;
+
+        if used_ports.insert(port) {
+          break port;
+        }
+      };
+
+      let (inbound, rule) = batch_inbound(i, port);
+      inbounds.push(inbound);
+      outbounds.extend(ep_outbounds);
+      rules.push(rule);
+      self.test_ports.push(Some(port));
+    }
+
+    self.port = self.test_ports.iter().flatten().next().copied();
+    Ok((inbounds, rules))
+  }
+}
+
+impl Drop for Xray {
+  /// 测速被取消时任务直接被丢弃，来不及调用 stop，在这里结束进程
+  fn drop(&mut self) {
+    if let Some(child) = self.child.take() {
+      debug!("Killing {}", self.display_name());
+      let _ = child.kill();
+
+      if let Some(filename) = self.filename.take() {
+        let _ = std::fs::remove_file(filename);
+      }
+    }
+  }
+}
+
+/// 路由规则，不包括最后把全部流量转给节点的规则
+pub fn routing_rules(rule: &str) -> Vec<Value> {
+  let mut rules = Vec::new();
+
+  match rule {
+    // 默认路由规则
+    "default" => {
+      rules.push(json!({
+        "type": "field",
+        "outboundTag": "block",
+        "domain": [
+          "activity.meteor.com",
+          "geosite:category-ads-all",
+        ]
+      }));
+      rules.push(json!({
+        "type": "field",
+        "outboundTag": "direct",
+        "domain": [
+          "domain:cypress.io",
+          "geosite:cn",
+          "geosite:private",
+          "geosite:apple-cn",
+          "geosite:google-cn",
+          "geosite:tld-cn",
+          "geosite:category-games@cn",
+        ],
+      }));
+      rules.push(json!({
+        "type": "field",
+        "outboundTag": "direct",
+        "ip": [
+          "8.8.8.8/32",
+          "223.5.5.5/32",
+          "119.29.29.29/32",
+          "180.76.76.76/32",
+          "114.114.114.114/32",
+          "geoip:private",
+          "geoip:cn",
+        ],
+      }));
+    }
+
+    // 代理全部数据
+    "all" => {
+      rules.push(json!({
+        "type": "field",
+        "outboundTag": "direct",
+        "domain": [
+          "domain:cypress.io",
+          "geosite:private",
+        ],
+      }));
+      rules.push(json!({
+        "type": "field",
+        "outboundTag": "direct",
+        "ip": [
+          "8.8.8.8/32",
+          "223.5.5.5/32",
+          "119.29.29.29/32",
+          "180.76.76.76/32",
+          "114.114.114.114/32",
+          "geoip:private",
+        ],
+      }));
+    }
+
+    // 测试不加任何规则
+    _ => {}
+  }
+
+  rules
+}
+
+/// 生成节点的出站对象；有前置代理时，依次生成整个链路
+async fn endpoint_outbounds(app: &AppHandle, ep: &Endpoint, tag: &str) -> Result<Vec<Value>> {
+  let settings = get_settings(app).await?;
+  let upstreams = resolve_upstreams(app, ep).await?;
+  let chain: Vec<&Endpoint> = std::iter::once(ep).chain(upstreams.iter()).collect();
+  let mut outbounds = Vec::new();
+
+  for (i, ep) in chain.iter().enumerate() {
+    // 叠加用户对节点的修改
+    let ep_settings = get_endpoint_settings(app, &ep.host, ep.port)
+      .await?
+      .unwrap_or_default();
+    let mut outbound = ep.outbound_with_patch(ep_settings.patch.as_deref())?;
+
+    // 导入的出站对象可能带有自己的 tag，统一改掉
+    if let Some(obj) = outbound.as_object_mut() {
+      obj.insert(String::from("tag"), json!(hop_tag(tag, i)));
+    }
+
+    // 多路复用和分片，节点设置优先
+    let mux = ep_settings
+      .mux
+      .map(|mux| mux.0)
+      .unwrap_or_else(|| settings.mux.clone());
+    apply_mux(&mut outbound, &mux);
+    apply_tls_overrides(&mut outbound, &ep.host, &settings);
+
+    let dialer = if i + 1 < chain.len() {
+      // 通过下一跳拨号
+      Some(hop_tag(tag, i + 1))
+    } else {
+      // 最后一跳直接连接服务器，需要时通过分片出站拨号
+      let fragment = ep_settings
+        .fragment
+        .map(|fragment| fragment.0)
+        .unwrap_or_else(|| settings.fragment.clone());
+
+      if fragment.is_active() {
+        let fragment_tag = format!("fragment-{}", tag);
+        outbounds.push(fragment_outbound(&fragment_tag, &fragment));
+        Some(fragment_tag)
+      } else {
+        None
+      }
+    };
+
+    if let Some(dialer) = dialer {
+      json_merge(
+        &mut outbound,
+        json!({
+          "streamSettings": {
+            "sockopt": {
+              "dialerProxy": dialer,
+            }
+          }
+        }),
+      );
+    }
+
+    outbounds.push(outbound);
+  }
+
+  Ok(outbounds)
+}
+
+/// 链路中第 i 跳的出站标签，第 0 跳即节点本身
+///
+/// 负载均衡按前缀选择出站，其他出站的 tag 不能以节点的 tag 开头。
+fn hop_tag(tag: &str, i: usize) -> String {
+  if i == 0 {
+    String::from(tag)
+  } else {
+    format!("hop{}-{}", i, tag)
+  }
+}
+
+/// 批量测试时第 i 个节点的出站标签
+fn batch_tag(i: usize) -> String {
+  format!("proxy-{}", i)
+}
+
+/// 批量测试时第 i 个节点的 socks 入站，以及把它路由到节点出站的规则
+fn batch_inbound(i: usize, port: u16) -> (Value, Value) {
+  let inbound_tag = format!("socks-{}", i);
+  let rule = json!({
+    "type": "field",
+    "inboundTag": [inbound_tag],
+    "outboundTag": batch_tag(i),
+  });
+
+  (test_inbound_object(&inbound_tag, port), rule)
+}
+
+/// 按全局设置调整 TLS 指纹和 allowInsecure
+fn apply_tls_overrides(outbound: &mut Value, host: &str, settings: &Settings) {
+  let Some(stream) = outbound.get_mut("streamSettings") else {
+    return;
+  };
+
+  for key in ["tlsSettings", "realitySettings"] {
+    let Some(tls) = stream.get_mut(key).filter(|tls| tls.is_object()) else {
+      continue;
+    };
+
+    // 链接未指定指纹时使用默认值
+    let fingerprint = tls["fingerprint"].as_str().unwrap_or_default();
+    if fingerprint.is_empty() && !settings.fingerprint.is_empty() {
+      tls["fingerprint"] = json!(settings.fingerprint);
+    }
+
+    // REALITY 没有 allowInsecure
+    if key != "tlsSettings" {
+      continue;
+    }
+
+    match settings.allow_insecure {
+      AllowInsecure::Link => {}
+      AllowInsecure::ForceOff => {
+        tls["allowInsecure"] = json!(false);
+      }
+      AllowInsecure::ForceOnForHosts => {
+        let server_name = tls["serverName"].as_str().unwrap_or_default();
+        let listed = settings
+          .allow_insecure_hosts
+          .iter()
+          .any(|listed| listed == host || listed == server_name);
+
+        // 未列出的主机保留链接中的值
+        if listed {
+          tls["allowInsecure"] = json!(true);
+        }
+      }
+    }
+  }
 }
 
-// impl Drop for Xray {
-//   fn drop(&mut self) {
-//     if self.child.is_some() {
-//       let _ = tauri::async_runtime::block_on(self.stop());
-//     }
-//   }
-// }
+/// 生成用于 TLS 分片和 UDP 噪声的 freedom 出站
+fn fragment_outbound(tag: &str, fragment: &Fragment) -> Value {
+  let mut settings = json!({});
+
+  if !fragment.packets.is_empty() {
+    settings["fragment"] = json!({
+      "packets": fragment.packets,
+      "length": fragment.length,
+      "interval": fragment.interval,
+    });
+  }
+
+  if !fragment.noises.is_empty() {
+    settings["noises"] = json!(fragment.noises);
+  }
+
+  json!({
+    "tag": tag,
+    "protocol": "freedom",
+    "settings": settings,
+    "streamSettings": {
+      "sockopt": {
+        "tcpNoDelay": true,
+      }
+    }
+  })
+}
+
+/// 可以启用多路复用的协议
+const MUX_PROTOCOLS: [&str; 6] = ["vmess", "vless", "trojan", "shadowsocks", "socks", "http"];
+
+/// 检查出站对象是否可以启用多路复用
+fn mux_supported(outbound: &Value) -> bool {
+  let protocol = outbound["protocol"].as_str().unwrap_or_default();
+
+  if !MUX_PROTOCOLS.contains(&protocol) {
+    return false;
+  }
+
+  // XTLS 流控（如 xtls-rprx-vision）与 mux 不兼容
+  let flow = outbound["settings"]["vnext"][0]["users"][0]["flow"]
+    .as_str()
+    .or(outbound["settings"]["flow"].as_str())
+    .unwrap_or_default();
+  flow.is_empty()
+}
+
+/// 向出站对象中加入多路复用设置
+fn apply_mux(outbound: &mut Value, mux: &Mux) {
+  if !mux.enabled {
+    return;
+  }
+
+  if !mux_supported(outbound) {
+    debug!("Mux is not supported by {}", outbound["protocol"]);
+    return;
+  }
+
+  outbound["mux"] = json!({
+    "enabled": true,
+    "concurrency": mux.concurrency,
+    "xudpConcurrency": mux.xudp_concurrency,
+    "xudpProxyUDP443": mux.xudp_proxy_udp443,
+  });
+}
 
 /// 获取入站配置
 async fn get_inbound_objects(for_test: bool) -> Result<(Vec<Value>, u16)> {
@@ -337,21 +724,7 @@ async fn get_inbound_objects(for_test: bool) -> Result<(Vec<Value>, u16)> {
 
   if for_test {
     // 测试用，只有 socks 入站
-    inbounds.push(json!({
-      "tag": "socks",
-      "port": port,
-      "listen": "127.0.0.1",
-      "protocol": "socks",
-      "sniffing": {
-        "enabled": true,
-        "destOverride": ["http", "tls"],
-        "routeOnly": false,
-      },
-      "settings": {
-        "auth": "noauth",
-        "udp": true,
-      },
-    }));
+    inbounds.push(test_inbound_object("socks", port));
   } else {
     // 正常用，生成 socks、http 和 API 入站
     // 用户配置
@@ -400,6 +773,25 @@ async fn get_inbound_objects(for_test: bool) -> Result<(Vec<Value>, u16)> {
   Ok((inbounds, port))
 }
 
+/// 测试用的 socks 入站
+fn test_inbound_object(tag: &str, port: u16) -> Value {
+  json!({
+    "tag": tag,
+    "port": port,
+    "listen": "127.0.0.1",
+    "protocol": "socks",
+    "sniffing": {
+      "enabled": true,
+      "destOverride": ["http", "tls"],
+      "routeOnly": false,
+    },
+    "settings": {
+      "auth": "noauth",
+      "udp": true,
+    },
+  })
+}
+
 async fn send_event(app: &Option<AppHandle>, pid: u32, event: CommandEvent) -> Result<bool> {
   if let Some(ref app) = app {
     match event {
@@ -428,3 +820,188 @@ async fn send_event(app: &Option<AppHandle>, pid: u32, event: CommandEvent) -> R
 
   Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::settings::Noise;
+
+  #[test]
+  fn mux_supported_by_protocol_and_flow() {
+    assert!(mux_supported(&json!({ "protocol": "vmess" })));
+    assert!(mux_supported(&json!({
+      "protocol": "vless",
+      "settings": { "vnext": [{ "users": [{ "flow": "" }] }] }
+    })));
+    assert!(!mux_supported(&json!({
+      "protocol": "vless",
+      "settings": { "vnext": [{ "users": [{ "flow": "xtls-rprx-vision" }] }] }
+    })));
+    assert!(!mux_supported(&json!({
+      "protocol": "trojan",
+      "settings": { "flow": "xtls-rprx-direct" }
+    })));
+    assert!(!mux_supported(&json!({ "protocol": "wireguard" })));
+    assert!(!mux_supported(&json!({})));
+  }
+
+  #[test]
+  fn apply_mux_only_when_enabled_and_supported() {
+    let mux = Mux {
+      enabled: true,
+      ..Default::default()
+    };
+
+    let mut outbound = json!({ "protocol": "trojan" });
+    apply_mux(&mut outbound, &mux);
+    assert_eq!(outbound["mux"]["enabled"], json!(true));
+    assert_eq!(outbound["mux"]["concurrency"], json!(8));
+    assert_eq!(outbound["mux"]["xudpProxyUDP443"], json!("reject"));
+
+    let mut outbound = json!({ "protocol": "wireguard" });
+    apply_mux(&mut outbound, &mux);
+    assert!(outbound.get("mux").is_none());
+
+    let mut outbound = json!({ "protocol": "trojan" });
+    apply_mux(&mut outbound, &Mux::default());
+    assert!(outbound.get("mux").is_none());
+  }
+
+  #[test]
+  fn fragment_outbound_settings() {
+    let fragment = Fragment {
+      enabled: true,
+      ..Default::default()
+    };
+    let outbound = fragment_outbound("proxy-fragment", &fragment);
+    assert_eq!(outbound["tag"], json!("proxy-fragment"));
+    assert_eq!(outbound["protocol"], json!("freedom"));
+    assert_eq!(
+      outbound["settings"]["fragment"]["packets"],
+      json!("tlshello")
+    );
+    assert!(outbound["settings"].get("noises").is_none());
+
+    // 只有噪声时不分片，但总开关仍然要打开
+    let fragment = Fragment {
+      enabled: true,
+      packets: String::new(),
+      noises: vec![Noise {
+        kind: String::from("rand"),
+        packet: String::from("10-20"),
+        delay: String::from("10-16"),
+      }],
+      ..Default::default()
+    };
+    let outbound = fragment_outbound("proxy-fragment", &fragment);
+    assert!(fragment.is_active());
+    assert!(outbound["settings"].get("fragment").is_none());
+    assert_eq!(outbound["settings"]["noises"][0]["type"], json!("rand"));
+
+    let fragment = Fragment {
+      enabled: false,
+      ..fragment
+    };
+    assert!(!fragment.is_active());
+  }
+
+  /// 在必填设置的基础上叠加指定字段
+  fn settings(extra: Value) -> Settings {
+    let mut settings = json!({
+      "socksPort": 1089,
+      "httpPort": 1090,
+      "allowLan": false,
+      "subUpdateInterval": 60,
+      "epTestInterval": 3,
+      "epTestConcurrency": 32,
+      "epTestUrl": "https://www.google.com/generate_204",
+      "rule": "default",
+    });
+    json_merge(&mut settings, extra);
+    serde_json::from_value(settings).unwrap()
+  }
+
+  #[test]
+  fn default_fingerprint_only_when_missing() {
+    let settings = settings(json!({ "fingerprint": "chrome" }));
+    let mut outbound = json!({
+      "streamSettings": {
+        "tlsSettings": { "serverName": "a.com" },
+        "realitySettings": { "fingerprint": "firefox" },
+      }
+    });
+    apply_tls_overrides(&mut outbound, "a.com", &settings);
+
+    let stream = &outbound["streamSettings"];
+    assert_eq!(stream["tlsSettings"]["fingerprint"], json!("chrome"));
+    assert_eq!(stream["realitySettings"]["fingerprint"], json!("firefox"));
+    // 按链接设置时不改动 allowInsecure
+    assert!(stream["tlsSettings"].get("allowInsecure").is_none());
+  }
+
+  #[test]
+  fn allow_insecure_policies() {
+    let outbound = json!({
+      "streamSettings": {
+        "tlsSettings": { "serverName": "sni.com", "allowInsecure": true },
+        "realitySettings": {},
+      }
+    });
+
+    let mut forced_off = outbound.clone();
+    apply_tls_overrides(
+      &mut forced_off,
+      "a.com",
+      &settings(json!({ "allowInsecure": "forceOff" })),
+    );
+    let stream = &forced_off["streamSettings"];
+    assert_eq!(stream["tlsSettings"]["allowInsecure"], json!(false));
+    assert!(stream["realitySettings"].get("allowInsecure").is_none());
+
+    let settings = settings(json!({
+      "allowInsecure": "forceOnForHosts",
+      "allowInsecureHosts": ["sni.com"],
+    }));
+    let mut listed = outbound.clone();
+    apply_tls_overrides(&mut listed, "a.com", &settings);
+    assert_eq!(
+      listed["streamSettings"]["tlsSettings"]["allowInsecure"],
+      json!(true)
+    );
+
+    // 未列出的主机保留链接中的值
+    let mut unlisted = json!({ "streamSettings": { "tlsSettings": { "allowInsecure": true } } });
+    apply_tls_overrides(&mut unlisted, "b.com", &settings);
+    assert_eq!(
+      unlisted["streamSettings"]["tlsSettings"]["allowInsecure"],
+      json!(true)
+    );
+
+    let mut unlisted = json!({ "streamSettings": { "tlsSettings": {} } });
+    apply_tls_overrides(&mut unlisted, "b.com", &settings);
+    assert!(unlisted["streamSettings"]["tlsSettings"]
+      .get("allowInsecure")
+      .is_none());
+  }
+
+  #[test]
+  fn batch_inbounds_route_to_own_outbound() {
+    let (inbound, rule) = batch_inbound(2, 10802);
+    assert_eq!(inbound["tag"], json!("socks-2"));
+    assert_eq!(inbound["port"], json!(10802));
+    assert_eq!(inbound["protocol"], json!("socks"));
+    assert_eq!(rule["inboundTag"], json!(["socks-2"]));
+    assert_eq!(rule["outboundTag"], json!(batch_tag(2)));
+    assert_ne!(batch_tag(1), batch_tag(2));
+  }
+
+  #[test]
+  fn hop_tags_are_unique_per_endpoint() {
+    assert_eq!(hop_tag("proxy", 0), "proxy");
+    assert_eq!(hop_tag("proxy-1", 0), "proxy-1");
+    assert_eq!(hop_tag("proxy-1", 2), "hop2-proxy-1");
+    assert!(!hop_tag("proxy", 1).starts_with("proxy"));
+    // 批量测试时不同节点的链路不会共用标签
+    assert_ne!(hop_tag(&batch_tag(1), 1), hop_tag(&batch_tag(2), 1));
+  }
+}
@@ -17,6 +17,13 @@ export function dbCountEndpoints() {
     return invoke()<number>("db_count_endpoints")
 }
 
+/**
+ * 按订阅统计节点数量
+ */
+export function dbCountEndpointsBySubscription() {
+    return invoke()<SubscriptionStats[]>("db_count_endpoints_by_subscription")
+}
+
 /**
  * 查询订阅数量
  */
@@ -119,7 +126,7 @@ export function getCurrentEndpoint() {
  * 给所有节点测速，并选择最快的节点
  */
 export function selectFastestEndpoint() {
-    return invoke()<number>("select_fastest_endpoint")
+    return invoke()<number | null>("select_fastest_endpoint")
 }
 
 /**
@@ -150,14 +157,284 @@ export function dbGetUpdatingSubscriptionIds() {
     return invoke()<number[]>("db_get_updating_subscription_ids")
 }
 
+/**
+ * 导入 xray 出站对象或完整配置，作为本地订阅保存
+ */
+export function importOutbounds(name: string, json: string) {
+    return invoke()<number>("import_outbounds", { name,json })
+}
+
+/**
+ * 获取节点的前置代理
+ */
+export function getEndpointUpstream(epId: number) {
+    return invoke()<number | null>("get_endpoint_upstream", { epId })
+}
+
+/**
+ * 设置节点的前置代理，为空时直接连接
+ */
+export function setEndpointUpstream(epId: number, upstreamId: number | null) {
+    return invoke()<null>("set_endpoint_upstream", { epId,upstreamId })
+}
+
+/**
+ * 获取节点的多路复用设置，为空时使用全局设置
+ */
+export function getEndpointMux(epId: number) {
+    return invoke()<Mux | null>("get_endpoint_mux", { epId })
+}
+
+/**
+ * 设置节点的多路复用，为空时使用全局设置
+ */
+export function setEndpointMux(epId: number, mux: Mux | null) {
+    return invoke()<null>("set_endpoint_mux", { epId,mux })
+}
+
+/**
+ * 获取节点的分片设置，为空时使用全局设置
+ */
+export function getEndpointFragment(epId: number) {
+    return invoke()<Fragment | null>("get_endpoint_fragment", { epId })
+}
+
+/**
+ * 设置节点的分片，为空时使用全局设置
+ */
+export function setEndpointFragment(epId: number, fragment: Fragment | null) {
+    return invoke()<null>("set_endpoint_fragment", { epId,fragment })
+}
+
+/**
+ * 快速开关 TLS 分片，无需重新导入订阅
+ */
+export function setFragmentEnabled(enabled: boolean) {
+    return invoke()<null>("set_fragment_enabled", { enabled })
+}
+
+/**
+ * 获取节点详情
+ */
+export function dbGetEndpointDetails(epId: number) {
+    return invoke()<EndpointDetails>("db_get_endpoint_details", { epId })
+}
+
+/**
+ * 按条件查找节点详情
+ */
+export function dbSearchEndpointDetails(filter: EndpointFilter) {
+    return invoke()<EndpointDetails[]>("db_search_endpoint_details", { filter })
+}
+
+/**
+ * 获取用户对节点出站对象的修改
+ */
+export function getEndpointPatch(epId: number) {
+    return invoke()<string | null>("get_endpoint_patch", { epId })
+}
+
+/**
+ * 设置用户对节点出站对象的修改，更新订阅后仍然有效
+ */
+export function setEndpointPatch(epId: number, patch: string) {
+    return invoke()<null>("set_endpoint_patch", { epId,patch })
+}
+
+/**
+ * 清除用户对节点出站对象的修改
+ */
+export function clearEndpointPatch(epId: number) {
+    return invoke()<null>("clear_endpoint_patch", { epId })
+}
+
+/**
+ * 查询节点的测速记录
+ */
+export function dbQueryLatencyLogs(epId: number, limit: number) {
+    return invoke()<LatencyLog[]>("db_query_latency_logs", { epId,limit })
+}
+
+/**
+ * 统计节点最近 N 次测速的延迟
+ */
+export function dbGetLatencyStats(epId: number, lastN: number) {
+    return invoke()<LatencyStats>("db_get_latency_stats", { epId,lastN })
+}
+
+/**
+ * 测试节点的下载速度，不切换当前节点
+ */
+export function testSpeeds(epIds: number[]) {
+    return invoke()<null>("test_speeds", { epIds })
+}
+
+/**
+ * 测试一个节点，不切换当前节点
+ */
+export function testEndpointLatency(epId: number) {
+    return invoke()<Endpoint>("test_endpoint_latency", { epId })
+}
+
+/**
+ * 测试指定的节点，不切换当前节点
+ */
+export function testEndpointLatencies(epIds: number[]) {
+    return invoke()<Endpoint[]>("test_endpoint_latencies", { epIds })
+}
+
+/**
+ * 测试订阅下的全部节点，不切换当前节点
+ */
+export function testSubscriptionLatencies(subId: number) {
+    return invoke()<Endpoint[]>("test_subscription_latencies", { subId })
+}
+
+/**
+ * 取消测速；不指定 ID 时取消全部
+ */
+export function cancelTestRun(runId: number | null) {
+    return invoke()<null>("cancel_test_run", { runId })
+}
+
+/**
+ * 获取正在进行的测速 ID 列表
+ */
+export function getTestRunIds() {
+    return invoke()<number[]>("get_test_run_ids")
+}
+
+/**
+ * 查询节点访问站点的测速结果，按节点地址和端口对应；不指定站点时查询全部
+ */
+export function dbQueryWebsiteLatencies(websiteId: number | null) {
+    return invoke()<WebsiteLatency[]>("db_query_website_latencies", { websiteId })
+}
+
+/**
+ * 测试全部节点访问各个站点的延迟，不切换当前节点
+ */
+export function testWebsiteMatrix() {
+    return invoke()<null>("test_website_matrix")
+}
+
+/**
+ * 测试全部节点访问指定站点的延迟，并选择最快的节点
+ */
+export function selectFastestEndpointForWebsite(websiteId: number) {
+    return invoke()<number>("select_fastest_endpoint_for_website", { websiteId })
+}
+
+/**
+ * 诊断站点无法访问的原因：域名解析、直接访问、通过代理访问，以及会匹配的路由规则
+ */
+export function diagnoseWebsite(url: string) {
+    return invoke()<WebsiteDiagnosis>("diagnose_website", { url })
+}
+
+/**
+ * 获取本机网络状态；还没有检测过时立即检测
+ */
+export function getNetworkStatus() {
+    return invoke()<NetworkStatus>("get_network_status")
+}
+
+/**
+ * 获取节点的收藏和黑名单标记
+ */
+export function getEndpointFlags(epId: number) {
+    return invoke()<EndpointFlags>("get_endpoint_flags", { epId })
+}
+
+/**
+ * 收藏节点，自动选择时优先使用
+ */
+export function setEndpointFavorite(epId: number, favorite: boolean) {
+    return invoke()<null>("set_endpoint_favorite", { epId,favorite })
+}
+
+/**
+ * 把节点加入黑名单，自动选择时不使用；手动选择不受影响
+ */
+export function setEndpointBlacklisted(epId: number, blacklisted: boolean) {
+    return invoke()<null>("set_endpoint_blacklisted", { epId,blacklisted })
+}
+
+/**
+ * 当前节点是否已锁定
+ */
+export function getEndpointLocked() {
+    return invoke()<boolean>("get_endpoint_locked")
+}
+
+/**
+ * 锁定当前节点，本次运行期间自动选择和故障切换都不会更换节点
+ */
+export function setEndpointLocked(locked: boolean) {
+    return invoke()<null>("set_endpoint_locked", { locked })
+}
+
+/**
+ * 通过 xray API 查询负载均衡正在使用的节点；未启用负载均衡时为空
+ */
+export function getBalancerStatus() {
+    return invoke()<BalancerStatus | null>("get_balancer_status")
+}
+
+/**
+ * 使用节点分组，由 xray 对组内节点负载均衡
+ */
+export function setCurrentGroup(groupId: number) {
+    return invoke()<null>("set_current_group", { groupId })
+}
+
+/**
+ * 获取当前使用的节点分组
+ */
+export function getCurrentGroup() {
+    return invoke()<number | null>("get_current_group")
+}
+
+/**
+ * 查询节点分组
+ */
+export function dbQueryEndpointGroups() {
+    return invoke()<EndpointGroup[]>("db_query_endpoint_groups")
+}
+
+/**
+ * 插入节点分组，返回分组 ID
+ */
+export function dbInsertEndpointGroup(doc: EndpointGroup) {
+    return invoke()<number>("db_insert_endpoint_group", { doc })
+}
+
+/**
+ * 更新节点分组
+ */
+export function dbUpdateEndpointGroup(doc: EndpointGroup) {
+    return invoke()<null>("db_update_endpoint_group", { doc })
+}
+
+/**
+ * 删除节点分组
+ */
+export function dbRemoveEndpointGroup(id: number) {
+    return invoke()<null>("db_remove_endpoint_group", { id })
+}
+
 /**
  * 节点
  */
-export type Endpoint = { id: number; subId: number; uri: string; name: string; host: string; port: number; latency: number | null; outbound: string }
+export type Endpoint = { id: number; subId: number; uri: string; name: string; host: string; port: number; latency: number | null; outbound: string; disabled: boolean | null; disabledReason: string | null; jitter: number | null; loss: number | null; outcome: string | null; speed: number | null; region: string | null }
+/**
+ * 订阅的节点统计
+ */
+export type SubscriptionStats = { subId: number; total: number; disabled: number }
 /**
  * 设置
  */
-export type Settings = { socksPort: number; httpPort: number; allowLan: boolean; subUpdateInterval: number; epTestInterval: number; epTestConcurrency: number; epTestUrl: string; rule: string }
+export type Settings = { socksPort: number; httpPort: number; allowLan: boolean; subUpdateInterval: number; epTestInterval: number; epTestConcurrency: number; epTestUrl: string; probe: Probe; epTestSamples: number; epSelectWindow: number; selection: Selection; epSelectRegions: string[]; epPingMode: PingMode; speedTest: SpeedTest; balancer: Balancer; rule: string; mux: Mux; fragment: Fragment; fingerprint: string; allowInsecure: AllowInsecure; allowInsecureHosts: string[] }
 /**
  * 站点
  */
@@ -169,8 +446,107 @@ export type Log = { id: number; log: string }
 /**
  * 订阅分组
  */
-export type Subscription = { id: number; name: string; url: string; disabled: boolean | null }
+export type Subscription = { id: number; name: string; url: string; disabled: boolean | null; content: string | null }
 /**
  * 流量记录
  */
 export type Flow = { id: number; ts: number; download: number; upload: number }
+/**
+ * 多路复用设置
+ */
+export type Mux = { enabled: boolean; concurrency: number; xudpConcurrency: number; xudpProxyUdp443: string }
+/**
+ * TLS 分片与 UDP 噪声设置
+ */
+export type Fragment = { enabled: boolean; packets: string; length: string; interval: string; noises: Noise[] }
+/**
+ * UDP 噪声
+ */
+export type Noise = { type: string; packet: string; delay: string }
+/**
+ * allowInsecure 策略
+ */
+export type AllowInsecure = "link" | "forceOff" | "forceOnForHosts"
+/**
+ * 节点详情，由出站对象解析而来
+ */
+export type EndpointDetails = { id: number; protocol: string; transport: string; security: string; sni: string | null; fingerprint: string | null; flow: string | null; path: string | null; hostHeader: string | null; credential: string | null }
+/**
+ * 节点搜索条件，为空的条件不参与匹配
+ */
+export type EndpointFilter = { protocol: string | null; transport: string | null; security: string | null; text: string | null }
+/**
+ * 测速记录，按地址和端口保存，更新订阅后仍然有效
+ */
+export type LatencyLog = { id: number; host: string; port: number; ts: number; latency: number | null; outcome: string }
+/**
+ * 延迟统计
+ */
+export type LatencyStats = { samples: number; median: number | null; jitter: number | null; loss: number }
+/**
+ * 快速预检方式
+ */
+export type PingMode = "off" | "tcp" | "tls"
+/**
+ * 节点选择策略；得分相当于毫秒，越小越好
+ */
+export type Selection = { jitterWeight: number; lossPenalty: number; failurePenalty: number; failureWindow: number; speedBonus: number; switchThreshold: number; minDwell: number; failoverCandidates: number; slowLatency: number; slowChecks: number }
+/**
+ * 负载均衡设置
+ *
+ * 启用后当前节点和排名靠前的节点一起作为出站，由 xray 的 burstObservatory 检测，
+ * 不再定时检查当前节点。
+ */
+export type Balancer = { enabled: boolean; size: number; strategy: BalancerStrategy; interval: number }
+/**
+ * 负载均衡策略
+ */
+export type BalancerStrategy = "leastPing" | "leastLoad" | "random" | "roundRobin"
+/**
+ * 下载测速设置
+ */
+export type SpeedTest = { url: string; duration: number; maxMb: number; concurrency: number }
+/**
+ * 测试请求设置
+ */
+export type Probe = { method: ProbeMethod; expectedStatus: number[]; bodyRegex: string; followRedirects: boolean; timeout: number; realDelay: boolean }
+/**
+ * 测试请求方法
+ */
+export type ProbeMethod = "head" | "get"
+/**
+ * 节点访问各站点的测速结果，按地址和端口保存
+ */
+export type WebsiteLatency = { id: number; host: string; port: number; websiteId: number; ts: number; latency: number | null; outcome: string }
+/**
+ * 站点诊断结果
+ */
+export type WebsiteDiagnosis = { url: string; host: string; dns: DiagnoseStep; direct: DiagnoseStep; proxy: DiagnoseStep; route: RouteMatch }
+/**
+ * 诊断的一个步骤
+ */
+export type DiagnoseStep = { ok: boolean; elapsed: number; detail: string | null; error: string | null }
+/**
+ * 匹配到的路由规则
+ */
+export type RouteMatch = { index: number | null; outboundTag: string; matched: string | null; error: string | null }
+/**
+ * 本机网络状态，变化时通过 app://network 事件发送
+ */
+export type NetworkStatus = "online" | "offline" | "captivePortal"
+/**
+ * 节点的收藏和黑名单标记
+ */
+export type EndpointFlags = { favorite: boolean; blacklisted: boolean }
+/**
+ * 负载均衡的状态
+ */
+export type BalancerStatus = { endpointIds: number[]; selectedIds: number[]; activeId: number | null }
+/**
+ * 节点分组，作为当前节点时对组内节点负载均衡
+ */
+export type EndpointGroup = { id: number; name: string; members: GroupMember[]; strategy: BalancerStrategy }
+/**
+ * 分组中的节点，按地址和端口保存，更新订阅后仍然有效
+ */
+export type GroupMember = { host: string; port: number }
@@ -1,11 +1,15 @@
 import { entity } from 'simpler-state';
-import { getCurrentEndpoint, type Endpoint } from './bindings';
+import { getCurrentEndpoint, getCurrentGroup, type Endpoint } from './bindings';
 
 export const current = entity(getCurrentEndpoint());
 
+/** 当前使用的节点分组 */
+export const currentGroup = entity(getCurrentGroup());
+
 export const reloadCurrent = async () => {
-  const id = await getCurrentEndpoint();
+  const [id, groupId] = await Promise.all([getCurrentEndpoint(), getCurrentGroup()]);
   current.set(id);
+  currentGroup.set(groupId);
 };
 
 /**
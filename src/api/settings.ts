@@ -10,7 +10,59 @@ const defaultSettings = Object.freeze<Settings>({
   epTestInterval: 3,
   epTestConcurrency: 32,
   epTestUrl: 'https://www.google.com/generate_204',
+  probe: {
+    method: 'head',
+    expectedStatus: [],
+    bodyRegex: '',
+    followRedirects: true,
+    timeout: 10,
+    realDelay: false,
+  },
+  epTestSamples: 3,
+  epSelectWindow: 0,
+  selection: {
+    jitterWeight: 1,
+    lossPenalty: 1000,
+    failurePenalty: 200,
+    failureWindow: 10,
+    speedBonus: 0,
+    switchThreshold: 20,
+    minDwell: 10,
+    failoverCandidates: 3,
+    slowLatency: 0,
+    slowChecks: 3,
+  },
+  epSelectRegions: [],
+  epPingMode: 'off',
+  speedTest: {
+    url: 'https://speed.cloudflare.com/__down?bytes=100000000',
+    duration: 10,
+    maxMb: 50,
+    concurrency: 2,
+  },
+  balancer: {
+    enabled: false,
+    size: 5,
+    strategy: 'leastPing',
+    interval: 30,
+  },
   rule: 'default',
+  mux: {
+    enabled: false,
+    concurrency: 8,
+    xudpConcurrency: 16,
+    xudpProxyUdp443: 'reject',
+  },
+  fragment: {
+    enabled: false,
+    packets: 'tlshello',
+    length: '100-200',
+    interval: '10-20',
+    noises: [],
+  },
+  fingerprint: 'chrome',
+  allowInsecure: 'link',
+  allowInsecureHosts: [],
 });
 
 const settings = entity(dbGetSettings());
@@ -0,0 +1,39 @@
+import { useEffect, useState } from 'react';
+import { getBalancerStatus, type BalancerStatus } from './bindings';
+import { current } from './currentEndpoint';
+
+/** 查询负载均衡状态的间隔，毫秒；xray 自行切换节点，没有事件通知 */
+const POLL_INTERVAL = 5000;
+
+const useBalancerStatus = () => {
+  const [status, setStatus] = useState<BalancerStatus | null>(null);
+  const cur = current.use();
+
+  useEffect(() => {
+    let active = true;
+
+    const poll = async () => {
+      try {
+        const value = await getBalancerStatus();
+
+        if (active) {
+          setStatus(value);
+        }
+      } catch (e) {
+        console.warn('Failed to get balancer status', e);
+      }
+    };
+
+    poll();
+    const timer = setInterval(poll, POLL_INTERVAL);
+
+    return () => {
+      active = false;
+      clearInterval(timer);
+    };
+  }, [cur]);
+
+  return status;
+};
+
+export default useBalancerStatus;
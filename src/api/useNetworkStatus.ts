@@ -0,0 +1,22 @@
+import type { Event } from '@tauri-apps/api/event';
+import { useCallback } from 'react';
+import { entity } from 'simpler-state';
+import { getNetworkStatus, type NetworkStatus } from './bindings';
+import useEvent from './useEvent';
+
+/** 本机网络状态 */
+const status = entity(getNetworkStatus());
+
+const useNetworkStatus = () => {
+  const s = status.use();
+
+  const handleStatus = useCallback((event: Event<NetworkStatus>) => {
+    status.set(event.payload);
+  }, []);
+
+  useEvent<NetworkStatus>('app://network', handleStatus);
+
+  return s;
+};
+
+export default useNetworkStatus;
@@ -0,0 +1,25 @@
+import type { Event } from '@tauri-apps/api/event';
+import { useCallback } from 'react';
+import { entity } from 'simpler-state';
+import useEvent from './useEvent';
+
+type TestProgress = { runId: number; tested: number; total: number; eta: number | null; done: boolean };
+
+/** 正在进行的测速进度，按测速 ID 索引 */
+const progresses = entity<Record<number, TestProgress>>({});
+
+const useTestProgress = () => {
+  const p = progresses.use();
+
+  const handleProgress = useCallback((event: Event<TestProgress>) => {
+    const { [event.payload.runId]: _, ...rest } = progresses.get();
+
+    progresses.set(event.payload.done ? rest : { ...rest, [event.payload.runId]: event.payload });
+  }, []);
+
+  useEvent<TestProgress>('app://test/progress', handleProgress);
+
+  return Object.values(p);
+};
+
+export default useTestProgress;
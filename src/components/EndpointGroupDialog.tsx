@@ -0,0 +1,113 @@
+import { forwardRef, useEffect, useState } from 'react';
+import type { BalancerStrategy, GroupMember } from '../api/bindings';
+import { endpoints } from '../db/endpoint';
+import { EndpointGroup } from '../db/endpointGroup';
+import TextInput from './TextInput';
+
+type EndpointGroupDialogProps = {
+  onClose: (values?: EndpointGroup) => Promise<void> | void;
+  group: EndpointGroup;
+};
+
+const strategies: { value: BalancerStrategy; label: string }[] = [
+  { value: 'leastPing', label: 'Least ping' },
+  { value: 'leastLoad', label: 'Least load' },
+  { value: 'random', label: 'Random' },
+  { value: 'roundRobin', label: 'Round robin' },
+];
+
+const isMember = (members: GroupMember[], host: string, port: number) =>
+  members.some((m) => m.host === host && m.port === port);
+
+const EndpointGroupDialog = forwardRef<HTMLDialogElement, EndpointGroupDialogProps>(
+  function EndpointGroupDialog(props, ref) {
+    const { onClose, group } = props;
+    const [values, setValues] = useState(group);
+    const items = (endpoints.use() ?? []).filter((ep) => !ep.disabled);
+
+    useEffect(() => {
+      setValues(group);
+    }, [group]);
+
+    const toggleMember = (host: string, port: number, checked: boolean) => {
+      const members = values.members.filter((m) => m.host !== host || m.port !== port);
+
+      if (checked) {
+        members.push({ host, port });
+      }
+
+      setValues({ ...values, members });
+    };
+
+    return (
+      <dialog className="modal" ref={ref}>
+        <div className="modal-box">
+          <h3 className="font-bold text-lg">Endpoint group</h3>
+          <form
+            autoComplete="off"
+            autoSave="off"
+            className="flex flex-col"
+            method="dialog"
+            onSubmit={() => onClose(values)}
+          >
+            <TextInput
+              name="name"
+              label="Name"
+              required
+              value={values.name}
+              onChange={(event) => setValues({ ...values, name: event.target.value })}
+            />
+            <div className="form-control w-full">
+              <label className="label">
+                <span className="label-text">Strategy</span>
+              </label>
+              <select
+                className="select select-bordered w-full"
+                value={values.strategy}
+                onChange={(event) =>
+                  setValues({ ...values, strategy: event.target.value as BalancerStrategy })
+                }
+              >
+                {strategies.map((s) => (
+                  <option key={s.value} value={s.value}>
+                    {s.label}
+                  </option>
+                ))}
+              </select>
+            </div>
+            <label className="label">
+              <span className="label-text">Endpoints (the first checked is the fallback)</span>
+            </label>
+            <div className="max-h-64 overflow-y-auto">
+              {items.map((ep) => (
+                <label key={ep.id} className="label cursor-pointer justify-start gap-2">
+                  <input
+                    type="checkbox"
+                    className="checkbox checkbox-sm checkbox-primary"
+                    checked={isMember(values.members, ep.host, ep.port)}
+                    onChange={(event) => toggleMember(ep.host, ep.port, event.target.checked)}
+                  />
+                  <span className="label-text truncate">{ep.name}</span>
+                </label>
+              ))}
+            </div>
+            <div className="modal-action">
+              <button className="btn" type="button" onClick={() => onClose()}>
+                Cancel
+              </button>
+              <button
+                className="btn btn-primary"
+                type="submit"
+                disabled={values.members.length === 0}
+              >
+                {group.id > 0 ? 'Save' : 'Add'}
+              </button>
+            </div>
+          </form>
+        </div>
+      </dialog>
+    );
+  },
+);
+
+export default EndpointGroupDialog;
@@ -0,0 +1,88 @@
+import DeleteIcon from '@material-symbols/svg-400/outlined/delete.svg?react';
+import EditIcon from '@material-symbols/svg-400/outlined/edit.svg?react';
+import React from 'react';
+import { dbRemoveEndpointGroup, dbUpdateEndpointGroup, setCurrentGroup } from '../api/bindings';
+import { currentGroup } from '../api/currentEndpoint';
+import { EndpointGroup, endpointGroups } from '../db/endpointGroup';
+import EndpointGroupDialog from './EndpointGroupDialog';
+
+export default function EndpointGroupList() {
+  const [group, setGroup] = React.useState<EndpointGroup>({
+    id: 0,
+    name: '',
+    members: [],
+    strategy: 'leastPing',
+  });
+  const ref = React.useRef<HTMLDialogElement>(null);
+  const items = endpointGroups.use() ?? [];
+  const cur = currentGroup.use();
+
+  if (items.length === 0) {
+    return null;
+  }
+
+  return (
+    <>
+      <table className="table">
+        <tbody>
+          {items.map((item) => (
+            <tr
+              key={item.id}
+              className={
+                item.id === cur ? 'bg-accent text-accent-content' : 'hover cursor-pointer'
+              }
+              onClick={() => setCurrentGroup(item.id)}
+            >
+              <td className="w-full">
+                <p className="text-lg font-bold">{item.name}</p>
+                <p className="text-sm opacity-50">{`${item.members.length} endpoints`}</p>
+              </td>
+              <td>
+                <div className="badge badge-sm">{item.strategy}</div>
+              </td>
+              <td>
+                <div className="join">
+                  <div className="tooltip tooltip-bottom" data-tip="Edit">
+                    <button
+                      className="btn btn-ghost btn-square join-item"
+                      onClick={(event) => {
+                        event.stopPropagation();
+                        setGroup(item);
+                        ref.current?.showModal();
+                      }}
+                    >
+                      <EditIcon />
+                    </button>
+                  </div>
+                  <div className="tooltip tooltip-bottom" data-tip="Remove">
+                    <button
+                      className="btn btn-error btn-square join-item"
+                      onClick={(event) => {
+                        event.stopPropagation();
+                        dbRemoveEndpointGroup(item.id);
+                      }}
+                    >
+                      <DeleteIcon />
+                    </button>
+                  </div>
+                </div>
+              </td>
+            </tr>
+          ))}
+        </tbody>
+      </table>
+      <div className="divider m-0 h-0" />
+      <EndpointGroupDialog
+        ref={ref}
+        onClose={async (values) => {
+          ref.current?.close();
+
+          if (values?.id) {
+            await dbUpdateEndpointGroup(values);
+          }
+        }}
+        group={group}
+      />
+    </>
+  );
+}
@@ -1,5 +1,6 @@
 import { setCurrentEndpoint } from '../api/bindings';
 import { current } from '../api/currentEndpoint';
+import useBalancerStatus from '../api/useBalancerStatus';
 import { endpoints } from '../db/endpoint';
 import { subscriptions } from '../db/subscription';
 import LatencyBadge from './LatencyBadge';
@@ -14,10 +15,19 @@ const getProtocol = (uri: string) => {
   return '<?>';
 };
 
+const formatSpeed = (speed: number) => {
+  if (speed >= 1024 * 1024) {
+    return `${(speed / 1024 / 1024).toFixed(1)} MB/s`;
+  }
+
+  return `${(speed / 1024).toFixed(0)} KB/s`;
+};
+
 export default function EndpointList() {
   const subs = subscriptions.use() ?? [];
   const items = endpoints.use() ?? [];
   const cur = current.use();
+  const balancer = useBalancerStatus();
 
   const getSubname = (subId?: number) => {
     const sub = subs.find((s) => s.id === subId);
@@ -30,11 +40,25 @@ export default function EndpointList() {
         {items.map((item) => (
           <tr
             key={item.id}
-            className={item.id === cur ? 'bg-accent text-accent-content' : 'hover cursor-pointer'}
-            onClick={() => setCurrentEndpoint(item.id)}
+            className={
+              item.disabled
+                ? 'opacity-50'
+                : item.id === cur
+                  ? 'bg-accent text-accent-content'
+                  : 'hover cursor-pointer'
+            }
+            onClick={() => !item.disabled && setCurrentEndpoint(item.id)}
           >
             <td className="w-full">
-              <p className="text-lg font-bold">{item.name}</p>
+              <p className="text-lg font-bold">
+                {item.name}
+                {balancer?.activeId === item.id && (
+                  <span className="badge badge-sm badge-success ms-2">Active</span>
+                )}
+                {balancer?.activeId !== item.id && balancer?.endpointIds.includes(item.id) && (
+                  <span className="badge badge-sm badge-outline ms-2">Balanced</span>
+                )}
+              </p>
               <p className="text-sm opacity-50 truncate max-w-xl">{`${item.host}:${item.port}`}</p>
             </td>
             <td>
@@ -43,8 +67,16 @@ export default function EndpointList() {
             <td>
               <div className="badge badge-sm">{getProtocol(item.uri)}</div>
             </td>
+            <td>{item.region && <div className="badge badge-sm font-mono">{item.region}</div>}</td>
             <td className="whitespace-nowrap text-end">
-              <LatencyBadge latency={item.latency ?? 0} />
+              {item.disabled ? (
+                <div className="badge badge-sm badge-ghost">{item.disabledReason ?? 'Disabled'}</div>
+              ) : (
+                <LatencyBadge latency={item.latency} outcome={item.outcome} />
+              )}
+              {item.speed !== null && (
+                <div className="badge badge-sm font-mono ms-1">{formatSpeed(item.speed)}</div>
+              )}
             </td>
           </tr>
         ))}
@@ -6,21 +6,40 @@ const ms = new Intl.NumberFormat(navigator.language, {
   unit: 'millisecond',
 });
 
+const failures: Record<string, string> = {
+  timeout: 'Timeout',
+  connectRefused: 'Refused',
+  dns: 'DNS Error',
+  tls: 'TLS Error',
+  bodyMismatch: 'Hijacked',
+  xrayFailed: 'Xray Failed',
+  other: 'Failed',
+};
+
 type LatencyBadgeProps = {
-  latency: number;
+  latency: number | null;
+  outcome: string | null;
 };
 
 export default function LatencyBadge(props: LatencyBadgeProps) {
-  const { latency } = props;
+  const { latency, outcome } = props;
   const [color, text] = React.useMemo(() => {
-    if (latency < 0) {
+    if (outcome === 'testing') {
       return ['badge-info', 'Testing'];
     }
 
-    if (latency === 0) {
+    if (!outcome) {
       return [null, 'Untested'];
     }
 
+    if (outcome.startsWith('http:')) {
+      return ['badge-ghost', `HTTP ${outcome.slice(5)}`];
+    }
+
+    if (outcome !== 'ok' || latency === null) {
+      return ['badge-ghost', failures[outcome] ?? 'Failed'];
+    }
+
     const text = ms.format(latency);
 
     if (latency <= 1000) {
@@ -31,12 +50,8 @@ export default function LatencyBadge(props: LatencyBadgeProps) {
       return ['badge-warning', text];
     }
 
-    if (latency <= 30000) {
-      return ['badge-error', text];
-    }
-
-    return ['badge-ghost', 'Timeout'];
-  }, [latency]);
+    return ['badge-error', text];
+  }, [latency, outcome]);
 
   return <div className={clsx('badge badge-sm font-mono', color)}>{text}</div>;
 }
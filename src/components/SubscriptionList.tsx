@@ -65,7 +65,7 @@ const SubscriptionRow = (props: SubscriptionRowProps) => {
 };
 
 export default function SubscriptionList() {
-  const [sub, setSub] = React.useState<Subscription>({ id: 0, name: '', url: '', disabled: null });
+  const [sub, setSub] = React.useState<Subscription>({ id: 0, name: '', url: '', disabled: null, content: null });
   const ref = React.useRef<HTMLDialogElement>(null);
   const items = subscriptions.use() ?? [];
   const updatings = updatingSubs.use() ?? [];
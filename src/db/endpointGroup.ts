@@ -0,0 +1,11 @@
+import { entity } from 'simpler-state';
+import { dbQueryEndpointGroups } from '../api/bindings';
+
+export type { EndpointGroup } from '../api/bindings';
+
+export const endpointGroups = entity(dbQueryEndpointGroups());
+
+export const reloadEndpointGroups = async () => {
+  const items = await dbQueryEndpointGroups();
+  endpointGroups.set(items);
+};
@@ -3,6 +3,7 @@ import { useEffect } from 'react';
 import { reloadCurrent } from '../api/currentEndpoint';
 import { reloadUpdatingSubs } from '../api/updatingSubs';
 import { reloadEndpoints } from './endpoint';
+import { reloadEndpointGroups } from './endpointGroup';
 import { reloadFlowLogs } from './flowLog';
 import { reloadLogs } from './logEntry';
 import { reloadSubscriptions } from './subscription';
@@ -13,6 +14,7 @@ const useListenDbChange = () => {
     const unlisten = Promise.all([
       listen('app://db/subscription', reloadSubscriptions),
       listen('app://db/endpoint', reloadEndpoints),
+      listen('app://db/endpoint_group_table', reloadEndpointGroups),
       listen('app://db/log', reloadLogs),
       listen('app://db/flow', reloadFlowLogs),
       listen('app://db/website', reloadWebsites),
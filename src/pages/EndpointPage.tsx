@@ -1,9 +1,27 @@
+import AddIcon from '@material-symbols/svg-400/outlined/add.svg?react';
 import NetworkCheckIcon from '@material-symbols/svg-400/outlined/network_check.svg?react';
-import { selectFastestEndpoint } from '../api/bindings';
+import React from 'react';
+import { dbInsertEndpointGroup, selectFastestEndpoint } from '../api/bindings';
 import CommandButton from '../components/CommandButton';
+import EndpointGroupDialog from '../components/EndpointGroupDialog';
+import EndpointGroupList from '../components/EndpointGroupList';
 import EndpointList from '../components/EndpointList';
+import { EndpointGroup } from '../db/endpointGroup';
 
 export default function EndpointPage() {
+  const ref = React.useRef<HTMLDialogElement>(null);
+
+  const addGroup = React.useCallback(
+    async (values?: EndpointGroup) => {
+      ref.current?.close();
+
+      if (values) {
+        await dbInsertEndpointGroup(values);
+      }
+    },
+    [ref],
+  );
+
   return (
     <div className="flex flex-col h-full">
       <div className="join p-2">
@@ -11,11 +29,21 @@ export default function EndpointPage() {
           <NetworkCheckIcon />
           Test latencies
         </CommandButton>
+        <button className="btn btn-ghost join-item" onClick={() => ref.current?.showModal()}>
+          <AddIcon />
+          New group
+        </button>
       </div>
       <div className="divider m-0 h-0" />
       <div className="min-h-0 grow overflow-y-auto">
+        <EndpointGroupList />
         <EndpointList />
       </div>
+      <EndpointGroupDialog
+        ref={ref}
+        onClose={addGroup}
+        group={{ id: 0, name: '', members: [], strategy: 'leastPing' }}
+      />
     </div>
   );
 }
@@ -7,6 +7,7 @@ import { selectFastestEndpoint, setCurrentEndpoint, type Settings } from '../api
 import { current } from '../api/currentEndpoint';
 import { updateSettings, useSettings } from '../api/settings';
 import useInputBox from '../api/useInputBox';
+import useNetworkStatus from '../api/useNetworkStatus';
 import FlowChart from '../components/FlowChart';
 import WebsiteSelectDialog from '../components/WebsiteSelectDialog';
 
@@ -25,6 +26,7 @@ export default function GeneralPage() {
   const settings = useSettings();
   const websiteRef = useRef<HTMLDialogElement>(null);
   const cur = current.use();
+  const network = useNetworkStatus();
 
   const updateAndRestart = async (delta: Partial<Settings>) => {
     await updateSettings(delta);
@@ -42,6 +44,15 @@ export default function GeneralPage() {
         Trebuchet <span className="text-base font-mono">{`v${version}`}</span>
       </h1>
 
+      {network === 'offline' && (
+        <div className="col-span-3 alert alert-warning">Network is offline, endpoint tests are paused.</div>
+      )}
+      {network === 'captivePortal' && (
+        <div className="col-span-3 alert alert-warning">
+          Network requires sign-in (captive portal), endpoint tests are paused.
+        </div>
+      )}
+
       <span>SOCKS5 port</span>
       <span className="font-mono text-end">{settings.socksPort}</span>
       <button
@@ -170,6 +181,30 @@ export default function GeneralPage() {
         }}
       />
 
+      <span>Auto-select regions</span>
+      <span className="font-mono text-end">
+        {settings.epSelectRegions.length ? settings.epSelectRegions.join(', ') : 'Any'}
+      </span>
+      <button
+        className="btn btn-sm btn-square btn-ghost"
+        onClick={async () => {
+          const value = await prompt({
+            label: 'Region codes for auto-select, e.g. JP, SG (empty for any):',
+            value: settings.epSelectRegions.join(', '),
+          });
+
+          if (value !== undefined) {
+            const epSelectRegions = value
+              .split(/[\s,]+/)
+              .map((region) => region.trim().toUpperCase())
+              .filter(Boolean);
+            await updateSettings({ epSelectRegions });
+          }
+        }}
+      >
+        <EditIcon />
+      </button>
+
       <span>Autostart</span>
       <label className="col-span-2 cursor-pointer label flex gap-2 justify-end p-0">
         <span>{autoStart ? 'Yes' : 'No'}</span>
@@ -40,7 +40,7 @@ export default function SubscriptionPage() {
       <SubscriptionDialog
         ref={ref}
         onClose={addSub}
-        sub={{ id: 0, name: '', url: '', disabled: null }}
+        sub={{ id: 0, name: '', url: '', disabled: null, content: null }}
       />
     </div>
   );